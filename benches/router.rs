@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use salvo_t::prelude::*;
+use salvo_t::routing::{CompiledRouter, PathState};
+use salvo_t::test::TestClient;
+
+/// `count` resources with the usual CRUD routes each, and a catch-all at the end.
+fn build_router(count: usize) -> Router {
+    let mut router = Router::new();
+    for i in 0..count {
+        router = router
+            .push(
+                Router::with_path(format!("api/v1/resource{}", i))
+                    .get(empty_handler)
+                    .post(empty_handler),
+            )
+            .push(
+                Router::with_path(format!("api/v1/resource{}/<id:num>", i))
+                    .get(empty_handler)
+                    .put(empty_handler)
+                    .delete(empty_handler),
+            );
+    }
+    router.push(Router::with_path("<**rest>").get(empty_handler))
+}
+
+fn bench_detect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("detect");
+    for count in [10, 100, 1000] {
+        let router = Arc::new(build_router(count));
+        let compiled = CompiledRouter::new(router.clone());
+        let url = format!("http://127.0.0.1/api/v1/resource{}/42", count - 1);
+        let mut req = TestClient::delete(&url).build();
+
+        group.bench_with_input(BenchmarkId::new("linear", count), &count, |b, _| {
+            b.iter(|| {
+                let mut path_state = PathState::new(req.uri().path());
+                black_box(router.detect(&mut req, &mut path_state))
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("compiled", count), &count, |b, _| {
+            b.iter(|| {
+                let mut path_state = PathState::new(req.uri().path());
+                black_box(compiled.detect(&mut req, &mut path_state))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_detect);
+criterion_main!(benches);
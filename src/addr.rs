@@ -1,8 +1,13 @@
+#[cfg(unix)]
+use std::sync::Arc;
+
 #[derive(Clone, Debug)]
 #[non_exhaustive]
 pub enum SocketAddr {
     IPv4(std::net::SocketAddrV4),
     IPv6(std::net::SocketAddrV6),
+    #[cfg(unix)]
+    Unix(Arc<tokio::net::unix::SocketAddr>),
 }
 impl From<std::net::SocketAddr> for SocketAddr {
     fn from(addr: std::net::SocketAddr) -> Self {
@@ -23,6 +28,13 @@ impl From<std::net::SocketAddrV6> for SocketAddr {
     }
 }
 
+#[cfg(unix)]
+impl From<tokio::net::unix::SocketAddr> for SocketAddr {
+    fn from(addr: tokio::net::unix::SocketAddr) -> Self {
+        SocketAddr::Unix(addr.into())
+    }
+}
+
 impl SocketAddr {
     pub fn is_ipv4(&self) -> bool {
         matches!(*self, SocketAddr::IPv4(_))
@@ -42,6 +54,26 @@ impl SocketAddr {
             _ => None,
         }
     }
+    /// IP of an IPv4 or IPv6 address, `None` for a unix socket.
+    pub fn ip(&self) -> Option<std::net::IpAddr> {
+        match self {
+            SocketAddr::IPv4(addr) => Some((*addr.ip()).into()),
+            SocketAddr::IPv6(addr) => Some((*addr.ip()).into()),
+            #[cfg(unix)]
+            SocketAddr::Unix(_) => None,
+        }
+    }
+    #[cfg(unix)]
+    pub fn is_unix(&self) -> bool {
+        matches!(*self, SocketAddr::Unix(_))
+    }
+    #[cfg(unix)]
+    pub fn as_unix(&self) -> Option<&tokio::net::unix::SocketAddr> {
+        match self {
+            SocketAddr::Unix(addr) => Some(addr),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for SocketAddr {
@@ -49,6 +81,11 @@ impl std::fmt::Display for SocketAddr {
         match self {
             SocketAddr::IPv4(addr) => write!(f, "socket://{}", addr),
             SocketAddr::IPv6(addr) => write!(f, "socket://{}", addr),
+            #[cfg(unix)]
+            SocketAddr::Unix(addr) => match addr.as_pathname() {
+                Some(path) => write!(f, "unix://{}", path.display()),
+                None => f.write_str("unix://"),
+            },
         }
     }
 }
@@ -62,6 +99,7 @@ mod tests {
         let ipv4: SocketAddr = ipv4.into();
         assert!(ipv4.is_ipv4());
         assert!(!ipv4.is_ipv6());
+        assert_eq!(ipv4.ip().unwrap().to_string(), "127.0.0.1");
     }
     #[tokio::test]
     async fn test_addr_ipv6() {
@@ -73,4 +111,16 @@ mod tests {
         assert!(ipv6.is_ipv6());
         assert!(!ipv6.is_ipv4());
     }
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_addr_unix() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("salvo.sock");
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let unix: SocketAddr = listener.local_addr().unwrap().into();
+        assert!(unix.is_unix());
+        assert!(!unix.is_ipv4());
+        assert!(unix.ip().is_none());
+        assert_eq!(unix.to_string(), format!("unix://{}", path.display()));
+    }
 }
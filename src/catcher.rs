@@ -1,18 +1,47 @@
+use std::fmt::Write;
+
 use hyper::{header, StatusCode};
 use mime::Mime;
 use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
 
 use crate::{
+    async_trait,
     depot::Depot,
-    http::{errors::StatusError, guess_accept_mime, request::Request, response::Response},
+    http::{
+        errors::StatusError, guess_accept_mime, negotiation::preferred_media_type,
+        request::Request, response::Response,
+    },
 };
 
 static SUPPORTED_FORMATS: Lazy<Vec<mime::Name>> =
     Lazy::new(|| vec![mime::JSON, mime::HTML, mime::XML, mime::PLAIN]);
+/// Formats `CatcherImpl` negotiates, the first one is used for `*/*`.
+static ERROR_FORMATS: Lazy<Vec<Mime>> = Lazy::new(|| {
+    [
+        "text/html",
+        "application/json",
+        "application/xml",
+        "text/xml",
+        "text/plain",
+        "application/problem+json",
+        "application/problem+xml",
+    ]
+    .iter()
+    .map(|format| format.parse().unwrap())
+    .collect()
+});
 const EMPTY_DETAIL_MSG: &str = "there is no more detailed explanation";
+/// Members of RFC 7807 problem details that extensions can not replace.
+const PROBLEM_MEMBERS: &[&str] = &["title", "status", "detail"];
 
+/// Renders error responses that have no body yet, returns `false` to leave it to the next one.
+///
+/// Catchers added with `Router::catcher` run first, innermost router first, then those of
+/// `Service::with_catchers` and finally `CatcherImpl`.
+#[async_trait]
 pub trait Catcher: Send + Sync + 'static {
-    fn catch(&self, req: &Request, depot: &Depot, res: &mut Response) -> bool;
+    async fn catch(&self, req: &mut Request, depot: &mut Depot, res: &mut Response) -> bool;
 }
 
 fn status_error_html(
@@ -108,11 +137,108 @@ fn status_error_xml(
         detail.unwrap_or(EMPTY_DETAIL_MSG)
     )
 }
+/// RFC 7807 problem details as a JSON object.
+fn problem_members(err: &StatusError) -> Map<String, Value> {
+    let mut members = err
+        .extensions
+        .iter()
+        .filter(|(name, _)| !PROBLEM_MEMBERS.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect::<Map<_, _>>();
+    members
+        .entry("type")
+        .or_insert_with(|| "about:blank".into());
+    members.insert("title".into(), err.name.as_str().into());
+    members.insert("status".into(), err.code.as_u16().into());
+    if let Some(detail) = err.detail.as_deref().or(err.summary.as_deref()) {
+        members.insert("detail".into(), detail.into());
+    }
+    members
+}
+fn problem_json(err: &StatusError) -> String {
+    Value::Object(problem_members(err)).to_string()
+}
+fn problem_xml(err: &StatusError) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><problem xmlns="urn:ietf:rfc:7807">"#,
+    );
+    for (name, value) in &problem_members(err) {
+        push_xml_element(&mut xml, name, value);
+    }
+    xml.push_str("</problem>");
+    xml
+}
+/// Arrays are written as `i` elements and objects as one element per member, as RFC 7807 does.
+///
+/// Members whose name is not an XML name, e.g. a field name from user input, are left out.
+fn push_xml_element(xml: &mut String, name: &str, value: &Value) {
+    if !is_xml_name(name) {
+        return;
+    }
+    let _ = write!(xml, "<{}>", name);
+    match value {
+        Value::Null => {}
+        Value::String(value) => xml.push_str(&escape_xml(value)),
+        Value::Array(items) => {
+            for item in items {
+                push_xml_element(xml, "i", item);
+            }
+        }
+        Value::Object(members) => {
+            for (name, value) in members {
+                push_xml_element(xml, name, value);
+            }
+        }
+        value => xml.push_str(&value.to_string()),
+    }
+    let _ = write!(xml, "</{}>", name);
+}
+/// The `Name` production of XML 1.0 without `:`, which would need a declared namespace prefix.
+fn is_xml_name(name: &str) -> bool {
+    fn is_start_char(ch: char) -> bool {
+        matches!(ch,
+            'A'..='Z' | '_' | 'a'..='z' | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}'
+            | '\u{F8}'..='\u{2FF}' | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}'
+            | '\u{200C}'..='\u{200D}' | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}'
+            | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}'
+            | '\u{10000}'..='\u{EFFFF}')
+    }
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_start_char)
+        && chars.all(|ch| {
+            is_start_char(ch)
+                || matches!(ch,
+                    '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+        })
+}
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+/// Renders `err` in `prefer_format`, `application/problem+json` and `application/problem+xml`
+/// included, falling back to HTML.
 pub fn status_error_bytes(err: &StatusError, prefer_format: &Mime) -> (Mime, Vec<u8>) {
-    let format = if !SUPPORTED_FORMATS.contains(&prefer_format.subtype()) {
+    if prefer_format.subtype() == "problem" {
+        match prefer_format.suffix().map(|s| s.as_str()) {
+            Some("json") => {
+                let format = "application/problem+json".parse().unwrap();
+                return (format, problem_json(err).into_bytes());
+            }
+            Some("xml") => {
+                let format = "application/problem+xml".parse().unwrap();
+                return (format, problem_xml(err).into_bytes());
+            }
+            _ => {}
+        }
+    }
+    let format: Mime = if !SUPPORTED_FORMATS.contains(&prefer_format.subtype()) {
         "text/html".parse().unwrap()
     } else {
-        prefer_format.clone()
+        prefer_format.essence_str().parse().unwrap()
     };
     let content = match format.subtype().as_ref() {
         "plain" => status_error_plain(
@@ -145,18 +271,24 @@ pub fn status_error_bytes(err: &StatusError, prefer_format: &Mime) -> (Mime, Vec
 
 pub struct CatcherImpl;
 
+#[async_trait]
 impl Catcher for CatcherImpl {
-    fn catch(&self, req: &Request, _depot: &Depot, res: &mut Response) -> bool {
+    async fn catch(&self, req: &mut Request, _depot: &mut Depot, res: &mut Response) -> bool {
         let status = res.status_code().unwrap_or(StatusCode::NOT_FOUND);
         if !status.is_server_error() && !status.is_client_error() {
             return false;
         }
-        let format = guess_accept_mime(req, None);
-        let (format, data) = if res.status_error.is_some() {
-            status_error_bytes(res.status_error.as_ref().unwrap(), &format)
-        } else {
-            status_error_bytes(&StatusError::from_code(status).unwrap(), &format)
+        let format = preferred_media_type(req, &ERROR_FORMATS)
+            .cloned()
+            .unwrap_or_else(|| guess_accept_mime(req, None));
+        let mut err = match &res.status_error {
+            Some(err) => err.clone(),
+            None => StatusError::from_code(status).unwrap(),
         };
+        err.extensions
+            .entry("instance")
+            .or_insert_with(|| req.uri().path().into());
+        let (format, data) = status_error_bytes(&err, &format);
         res.headers_mut()
             .insert(header::CONTENT_TYPE, format.to_string().parse().unwrap());
         res.write_body(data).ok();
@@ -185,8 +317,9 @@ mod tests {
     }
 
     struct Handle404;
+    #[async_trait]
     impl Catcher for Handle404 {
-        fn catch(&self, _req: &Request, _depot: &Depot, res: &mut Response) -> bool {
+        async fn catch(&self, _req: &mut Request, _depot: &mut Depot, res: &mut Response) -> bool {
             if let Some(StatusCode::NOT_FOUND) = res.status_code() {
                 res.render("Custom 404 Error Page");
                 true
@@ -237,4 +370,140 @@ mod tests {
 
         assert_eq!(access(&service, "notfound").await, "Custom 404 Error Page");
     }
+
+    struct BodyCatcher(&'static str);
+    #[async_trait]
+    impl Catcher for BodyCatcher {
+        async fn catch(&self, req: &mut Request, depot: &mut Depot, res: &mut Response) -> bool {
+            if res.status_code() != Some(StatusCode::BAD_REQUEST) {
+                return false;
+            }
+            let body = req.payload().await.map(|b| b.len()).unwrap_or_default();
+            let user = depot.get::<&str>("user").copied().unwrap_or("-");
+            res.render(format!("{} {} {}", self.0, user, body));
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router_catchers() {
+        #[handler(internal)]
+        async fn bad_request(depot: &mut Depot, res: &mut Response) {
+            depot.insert("user", "alice");
+            res.set_status_code(StatusCode::BAD_REQUEST);
+        }
+        let router = Router::new()
+            .catcher(BodyCatcher("root"))
+            .push(
+                Router::with_path("api")
+                    .catcher(BodyCatcher("api"))
+                    .push(Router::with_path("bad").post(bad_request)),
+            )
+            .push(Router::with_path("bad").post(bad_request));
+        let catchers: Vec<Box<dyn Catcher>> = vec![Box::new(Handle404)];
+        let service = Service::new(router).with_catchers(catchers);
+
+        async fn access(service: &Service, path: &str) -> String {
+            TestClient::post(format!("http://127.0.0.1:7878/{}", path))
+                .text("hello")
+                .send(service)
+                .await
+                .take_string()
+                .await
+                .unwrap()
+        }
+
+        assert_eq!(access(&service, "api/bad").await, "api alice 5");
+        assert_eq!(access(&service, "bad").await, "root alice 5");
+        assert_eq!(
+            access(&service, "api/missing").await,
+            "Custom 404 Error Page"
+        );
+
+        let mut req = TestClient::get("http://127.0.0.1:7878/api/missing").build();
+        let mut path_state = crate::routing::PathState::new(req.uri().path());
+        service.router().detect(&mut req, &mut path_state);
+        assert_eq!(path_state.catchers().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_problem_details() {
+        #[handler(internal)]
+        async fn invalid(res: &mut Response) {
+            res.set_status_error(
+                StatusError::unprocessable_entity()
+                    .with_detail("Name is <empty>.")
+                    .with_problem_type("https://example.com/probs/invalid")
+                    .with_extension("errors", serde_json::json!([{"field": "name"}]))
+                    .with_extension("status", 200),
+            );
+        }
+        let service = Service::new(Router::with_path("users").post(invalid));
+
+        let mut res = TestClient::post("http://127.0.0.1:7878/users")
+            .add_header(
+                header::ACCEPT,
+                "text/html;q=0.5, application/problem+json",
+                true,
+            )
+            .send(&service)
+            .await;
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let problem: Value = serde_json::from_str(&res.take_string().await.unwrap()).unwrap();
+        assert_eq!(
+            problem,
+            serde_json::json!({
+                "type": "https://example.com/probs/invalid",
+                "title": "Unprocessable Entity",
+                "status": 422,
+                "detail": "Name is <empty>.",
+                "instance": "/users",
+                "errors": [{"field": "name"}],
+            })
+        );
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/missing")
+            .add_header(
+                header::ACCEPT,
+                "image/webp, application/problem+xml;q=0.8, text/html;q=0.1",
+                true,
+            )
+            .send(&service)
+            .await;
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/problem+xml"
+        );
+        assert_eq!(
+            res.take_string().await.unwrap(),
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?><problem xmlns="urn:ietf:rfc:7807">"#,
+                "<detail>The requested resource could not be found.</detail>",
+                "<instance>/missing</instance><status>404</status>",
+                "<title>Not Found</title><type>about:blank</type></problem>"
+            )
+        );
+
+        let err = StatusError::bad_request()
+            .with_detail("a & b's")
+            .with_extension("errors", serde_json::json!(["x", {"y": null}]));
+        let (_, xml) = status_error_bytes(&err, &"application/problem+xml".parse().unwrap());
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains("<detail>a &amp; b&apos;s</detail>"));
+        assert!(xml.contains("<errors><i>x</i><i><y></y></i></errors>"));
+
+        let err = StatusError::bad_request()
+            .with_extension("a><script/><b", 1)
+            .with_extension(
+                "errors",
+                serde_json::json!([{"foo bar": 1, "x:y": 2, "1st": 3, "名前": 4, "_a-b.c": 5}]),
+            );
+        let (_, xml) = status_error_bytes(&err, &"application/problem+xml".parse().unwrap());
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(!xml.contains("script"));
+        assert!(xml.contains("<errors><i><_a-b.c>5</_a-b.c><名前>4</名前></i></errors>"));
+    }
 }
@@ -0,0 +1,138 @@
+use crate::http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use crate::http::{Request, Response, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Key of the authenticated username in the `Depot`.
+pub const USERNAME_KEY: &str = "::salvo::basic_auth::username";
+
+/// Checks the credentials of a `BasicAuth` request.
+#[async_trait]
+pub trait BasicAuthValidator: Send + Sync + 'static {
+    async fn validate(&self, username: &str, password: &str, depot: &mut Depot) -> bool;
+}
+
+/// Access to the username authenticated by `BasicAuth`.
+pub trait BasicAuthDepotExt {
+    fn basic_auth_username(&self) -> Option<&str>;
+}
+
+impl BasicAuthDepotExt for Depot {
+    fn basic_auth_username(&self) -> Option<&str> {
+        self.get::<String>(USERNAME_KEY).map(|s| &**s)
+    }
+}
+
+/// HTTP basic authentication, see [RFC 7617](https://www.rfc-editor.org/rfc/rfc7617).
+pub struct BasicAuth<V> {
+    realm: String,
+    validator: V,
+}
+
+impl<V: BasicAuthValidator> BasicAuth<V> {
+    pub fn new(validator: V) -> Self {
+        BasicAuth {
+            realm: "realm".into(),
+            validator,
+        }
+    }
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+    pub fn with_realm(mut self, realm: impl Into<String>) -> Self {
+        self.realm = realm.into();
+        self
+    }
+
+    fn ask_credentials(&self, res: &mut Response) {
+        let challenge = format!("Basic realm={:?}, charset=\"UTF-8\"", self.realm);
+        if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+            res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+        }
+        res.set_status_error(StatusError::unauthorized());
+    }
+}
+
+fn parse_credentials(req: &Request) -> Option<(String, String)> {
+    let auth = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = auth.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let credentials = String::from_utf8(base64::decode(credentials.trim()).ok()?).ok()?;
+    let (username, password) = credentials.split_once(':')?;
+    Some((username.to_owned(), password.to_owned()))
+}
+
+#[async_trait]
+impl<V: BasicAuthValidator> Handler for BasicAuth<V> {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        match parse_credentials(req) {
+            Some((username, password))
+                if self.validator.validate(&username, &password, depot).await =>
+            {
+                depot.insert(USERNAME_KEY, username);
+                ctrl.call_next(req, depot, res).await;
+            }
+            _ => {
+                self.ask_credentials(res);
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    struct Validator;
+    #[async_trait]
+    impl BasicAuthValidator for Validator {
+        async fn validate(&self, username: &str, password: &str, _depot: &mut Depot) -> bool {
+            username == "root" && password == "pass:word"
+        }
+    }
+
+    #[handler(internal)]
+    async fn hello(depot: &mut Depot) -> String {
+        format!("hello {}", depot.basic_auth_username().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_basic_auth() {
+        let router = Router::with_hoop(BasicAuth::new(Validator).with_realm("admin"))
+            .push(Router::with_path("hello").get(hello));
+        let service = Service::new(router);
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/hello")
+            .basic_auth("root", Some("pass:word"))
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello root");
+
+        let res = TestClient::get("http://127.0.0.1:7878/hello")
+            .basic_auth("root", Some("wrong"))
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(
+            res.headers()[WWW_AUTHENTICATE],
+            "Basic realm=\"admin\", charset=\"UTF-8\""
+        );
+
+        let res = TestClient::get("http://127.0.0.1:7878/hello")
+            .bearer_auth("token")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::UNAUTHORIZED));
+    }
+}
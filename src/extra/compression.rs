@@ -0,0 +1,329 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::Error as IoError;
+use std::str::FromStr;
+
+use async_compression::tokio::bufread::{BrotliEncoder, DeflateEncoder, GzipEncoder};
+use futures_util::TryStreamExt;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use super::add_vary;
+use crate::http::header::{
+    HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE,
+};
+use crate::http::negotiation::{parse_quality_values, token_quality};
+use crate::http::{Mime, Request, ResBody, Response, StatusCode};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+const DEFAULT_MIN_LENGTH: usize = 1024;
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Hash)]
+#[non_exhaustive]
+pub enum CompressionAlgo {
+    Brotli,
+    Deflate,
+    Gzip,
+}
+
+impl CompressionAlgo {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionAlgo::Brotli => "br",
+            CompressionAlgo::Deflate => "deflate",
+            CompressionAlgo::Gzip => "gzip",
+        }
+    }
+}
+
+impl FromStr for CompressionAlgo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "br" => Ok(CompressionAlgo::Brotli),
+            "deflate" => Ok(CompressionAlgo::Deflate),
+            "gzip" | "x-gzip" => Ok(CompressionAlgo::Gzip),
+            _ => Err(format!("unknown compression algorithm: {}", s)),
+        }
+    }
+}
+
+impl Display for CompressionAlgo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Compresses response bodies according to the request `Accept-Encoding` header.
+///
+/// Algorithms are tried in the order they were configured when the client weights them equally.
+pub struct Compression {
+    algos: Vec<CompressionAlgo>,
+    content_types: Vec<Mime>,
+    min_length: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Compression {
+            algos: vec![
+                CompressionAlgo::Brotli,
+                CompressionAlgo::Gzip,
+                CompressionAlgo::Deflate,
+            ],
+            content_types: vec![
+                mime::TEXT_STAR,
+                mime::APPLICATION_JAVASCRIPT,
+                mime::APPLICATION_JSON,
+                "application/xml".parse().unwrap(),
+                "application/rss+xml".parse().unwrap(),
+                "application/atom+xml".parse().unwrap(),
+                mime::IMAGE_SVG,
+                "application/wasm".parse().unwrap(),
+            ],
+            min_length: DEFAULT_MIN_LENGTH,
+        }
+    }
+    pub fn algos(&self) -> &Vec<CompressionAlgo> {
+        &self.algos
+    }
+    pub fn with_algos(mut self, algos: impl Into<Vec<CompressionAlgo>>) -> Self {
+        self.algos = algos.into();
+        self
+    }
+    pub fn content_types(&self) -> &Vec<Mime> {
+        &self.content_types
+    }
+    /// Sets the compressible content types, a `*` subtype such as `text/*` matches the whole type.
+    pub fn with_content_types(mut self, content_types: impl Into<Vec<Mime>>) -> Self {
+        self.content_types = content_types.into();
+        self
+    }
+    pub fn min_length(&self) -> usize {
+        self.min_length
+    }
+    /// Bodies with known size smaller than `min_length` are sent uncompressed.
+    pub fn with_min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    fn is_compressible(&self, ctype: &Mime) -> bool {
+        self.content_types.iter().any(|mime| {
+            mime.type_() == ctype.type_()
+                && (mime.subtype() == mime::STAR || mime.subtype() == ctype.subtype())
+        })
+    }
+
+    fn negotiate(&self, req: &Request) -> Option<CompressionAlgo> {
+        let header = req
+            .headers()
+            .get(ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())?;
+        let accepted = parse_quality_values(header);
+        let quality_of = |name: &str| token_quality(&accepted, name);
+
+        let mut best: Option<(CompressionAlgo, f32)> = None;
+        for algo in &self.algos {
+            let q = if *algo == CompressionAlgo::Gzip {
+                quality_of("gzip").or_else(|| quality_of("x-gzip"))
+            } else {
+                quality_of(algo.as_str())
+            }
+            .unwrap_or(0.0);
+            if q > 0.0 && best.map(|(_, best_q)| q > best_q).unwrap_or(true) {
+                best = Some((*algo, q));
+            }
+        }
+        let (algo, q) = best?;
+        // An explicitly preferred `identity` wins over a less weighted compression.
+        match accepted.iter().find(|v| v.value == "identity") {
+            Some(identity) if identity.quality > q => None,
+            _ => Some(algo),
+        }
+    }
+}
+
+fn encode_body(algo: CompressionAlgo, body: ResBody) -> ResBody {
+    let reader = StreamReader::new(body.map_err(IoError::other));
+    match algo {
+        CompressionAlgo::Brotli => ResBody::Stream(Box::pin(
+            ReaderStream::new(BrotliEncoder::new(reader)).map_err(Into::into),
+        )),
+        CompressionAlgo::Deflate => ResBody::Stream(Box::pin(
+            ReaderStream::new(DeflateEncoder::new(reader)).map_err(Into::into),
+        )),
+        CompressionAlgo::Gzip => ResBody::Stream(Box::pin(
+            ReaderStream::new(GzipEncoder::new(reader)).map_err(Into::into),
+        )),
+    }
+}
+
+#[async_trait]
+impl Handler for Compression {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        ctrl.call_next(req, depot, res).await;
+        if ctrl.is_ceased() || res.body().is_none() || res.headers().contains_key(CONTENT_ENCODING)
+        {
+            return;
+        }
+        if let Some(
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED | StatusCode::PARTIAL_CONTENT,
+        ) = res.status_code()
+        {
+            return;
+        }
+        let ctype = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<Mime>().ok());
+        match ctype {
+            Some(ctype) if self.is_compressible(&ctype) => {}
+            _ => return,
+        }
+        if let Some(size) = res.body().size() {
+            if size < self.min_length as u64 {
+                return;
+            }
+        }
+
+        add_vary(res, ACCEPT_ENCODING);
+        if let Some(algo) = self.negotiate(req) {
+            let body = res.take_body();
+            res.set_body(encode_body(algo, body));
+            res.headers_mut().remove(CONTENT_LENGTH);
+            res.headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(algo.as_str()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+    use crate::http::header::VARY;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    #[handler(internal)]
+    async fn hello() -> &'static str {
+        "hello hello hello hello hello hello hello hello hello hello"
+    }
+    #[handler(internal)]
+    async fn streamed(res: &mut Response) {
+        res.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        res.streaming(stream::iter(vec![
+            Ok::<_, std::io::Error>("hello "),
+            Ok("world"),
+        ]))
+        .unwrap();
+    }
+    #[handler(internal)]
+    async fn binary(res: &mut Response) {
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/octet-stream"),
+        );
+        res.write_body(vec![0u8; 2048]).unwrap();
+    }
+
+    fn router() -> Router {
+        Router::with_hoop(Compression::new().with_min_length(0))
+            .push(Router::with_path("hello").get(hello))
+            .push(Router::with_path("stream").get(streamed))
+            .push(Router::with_path("binary").get(binary))
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let compression = Compression::new();
+        let negotiate = |accept: &str| {
+            let req = TestClient::get("http://127.0.0.1:7878/")
+                .add_header(ACCEPT_ENCODING, accept, true)
+                .build();
+            compression.negotiate(&req)
+        };
+        assert_eq!(negotiate("gzip, deflate"), Some(CompressionAlgo::Gzip));
+        assert_eq!(
+            negotiate("gzip, deflate, br"),
+            Some(CompressionAlgo::Brotli)
+        );
+        assert_eq!(
+            negotiate("br;q=0.5, deflate;q=0.9"),
+            Some(CompressionAlgo::Deflate)
+        );
+        assert_eq!(negotiate("*"), Some(CompressionAlgo::Brotli));
+        assert_eq!(negotiate("br;q=0, *;q=0.2"), Some(CompressionAlgo::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, identity"), None);
+        assert_eq!(negotiate("compress"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+    }
+
+    #[tokio::test]
+    async fn test_compress_once() {
+        for algo in ["gzip", "deflate", "br"] {
+            let mut res = TestClient::get("http://127.0.0.1:7878/hello")
+                .add_header(ACCEPT_ENCODING, algo, true)
+                .send(router())
+                .await;
+            assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), algo);
+            assert_eq!(res.headers().get(VARY).unwrap(), "accept-encoding");
+            assert!(res.body().is_stream());
+            assert_eq!(
+                res.take_string().await.unwrap(),
+                "hello hello hello hello hello hello hello hello hello hello"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compress_stream() {
+        let mut res = TestClient::get("http://127.0.0.1:7878/stream")
+            .add_header(ACCEPT_ENCODING, "gzip", true)
+            .send(router())
+            .await;
+        assert_eq!(res.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(res.take_string().await.unwrap(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_skip_compress() {
+        let res = TestClient::get("http://127.0.0.1:7878/hello")
+            .send(router())
+            .await;
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+
+        let res = TestClient::get("http://127.0.0.1:7878/binary")
+            .add_header(ACCEPT_ENCODING, "gzip", true)
+            .send(router())
+            .await;
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+
+        let router = Router::with_hoop(Compression::new()).get(hello);
+        let mut res = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(ACCEPT_ENCODING, "gzip", true)
+            .send(router)
+            .await;
+        assert!(res.headers().get(CONTENT_ENCODING).is_none());
+        assert!(res.headers().get(VARY).is_none());
+        assert_eq!(
+            res.take_string().await.unwrap(),
+            "hello hello hello hello hello hello hello hello hello hello"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::http::{Request, Response, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Counters of a `ConcurrencyLimiter`, shared with the limiter so they can be read for metrics.
+#[derive(Clone, Debug, Default)]
+pub struct ConcurrencyStats {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    queued: AtomicUsize,
+    shed: AtomicUsize,
+}
+
+impl ConcurrencyStats {
+    /// Requests being handled right now.
+    pub fn current(&self) -> usize {
+        self.inner.current.load(Ordering::Acquire)
+    }
+    /// Highest number of requests handled at once.
+    pub fn peak(&self) -> usize {
+        self.inner.peak.load(Ordering::Acquire)
+    }
+    /// Requests waiting for a slot right now.
+    pub fn queued(&self) -> usize {
+        self.inner.queued.load(Ordering::Acquire)
+    }
+    /// Requests rejected since the limiter was created.
+    pub fn shed(&self) -> usize {
+        self.inner.shed.load(Ordering::Acquire)
+    }
+}
+
+/// Decrements a counter when dropped, so cancelled requests are accounted for too.
+struct CountGuard<'a>(&'a AtomicUsize);
+
+impl<'a> CountGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> (Self, usize) {
+        let count = counter.fetch_add(1, Ordering::AcqRel) + 1;
+        (CountGuard(counter), count)
+    }
+}
+
+impl Drop for CountGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Caps the number of requests handled at once by the handlers after it.
+///
+/// Requests over the limit are shed with `StatusError::service_unavailable()` right away,
+/// unless a queue is configured with `with_queue_timeout`, in which case they wait for a slot
+/// at most that long.
+pub struct ConcurrencyLimiter {
+    semaphore: Arc<Semaphore>,
+    max_concurrency: usize,
+    queue_timeout: Option<Duration>,
+    max_queued: Option<usize>,
+    stats: ConcurrencyStats,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max_concurrency: usize) -> Self {
+        ConcurrencyLimiter {
+            semaphore: Arc::new(Semaphore::new(max_concurrency)),
+            max_concurrency,
+            queue_timeout: None,
+            max_queued: None,
+            stats: ConcurrencyStats::default(),
+        }
+    }
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+    pub fn queue_timeout(&self) -> Option<Duration> {
+        self.queue_timeout
+    }
+    pub fn with_queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = Some(queue_timeout);
+        self
+    }
+    pub fn max_queued(&self) -> Option<usize> {
+        self.max_queued
+    }
+    /// Sheds requests right away when `max_queued` requests are waiting already.
+    pub fn with_max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = Some(max_queued);
+        self
+    }
+    pub fn stats(&self) -> ConcurrencyStats {
+        self.stats.clone()
+    }
+
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = self.semaphore.clone().try_acquire_owned() {
+            return Some(permit);
+        }
+        let queue_timeout = self.queue_timeout?;
+        let (_queued, queued) = CountGuard::new(&self.stats.inner.queued);
+        if matches!(self.max_queued, Some(max_queued) if queued > max_queued) {
+            return None;
+        }
+        tokio::time::timeout(queue_timeout, self.semaphore.clone().acquire_owned())
+            .await
+            .ok()
+            .and_then(Result::ok)
+    }
+}
+
+#[async_trait]
+impl Handler for ConcurrencyLimiter {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let _permit = match self.acquire().await {
+            Some(permit) => permit,
+            None => {
+                self.stats.inner.shed.fetch_add(1, Ordering::AcqRel);
+                res.set_status_error(StatusError::service_unavailable());
+                ctrl.skip_rest();
+                return;
+            }
+        };
+        let (_current, current) = CountGuard::new(&self.stats.inner.current);
+        self.stats.inner.peak.fetch_max(current, Ordering::AcqRel);
+        ctrl.call_next(req, depot, res).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future::join_all;
+
+    use crate::prelude::*;
+    use crate::test::TestClient;
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn slow() -> &'static str {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        "done"
+    }
+
+    async fn send_all(service: &Service, count: usize) -> Vec<Option<StatusCode>> {
+        join_all((0..count).map(|_| async {
+            TestClient::get("http://127.0.0.1:7878/")
+                .send(service)
+                .await
+                .status_code()
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limiter_shed() {
+        let limiter = ConcurrencyLimiter::new(2);
+        let stats = limiter.stats();
+        let service = Service::new(Router::with_hoop(limiter).get(slow));
+
+        let codes = send_all(&service, 4).await;
+        let ok = codes.iter().filter(|c| **c == Some(StatusCode::OK)).count();
+        let shed = codes
+            .iter()
+            .filter(|c| **c == Some(StatusCode::SERVICE_UNAVAILABLE))
+            .count();
+        assert_eq!((ok, shed), (2, 2));
+        assert_eq!(stats.peak(), 2);
+        assert_eq!(stats.shed(), 2);
+        assert_eq!(stats.current(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limiter_queue() {
+        let limiter = ConcurrencyLimiter::new(1).with_queue_timeout(Duration::from_secs(5));
+        let stats = limiter.stats();
+        let service = Service::new(Router::with_hoop(limiter).get(slow));
+        let codes = send_all(&service, 3).await;
+        assert!(codes.iter().all(|c| *c == Some(StatusCode::OK)));
+        assert_eq!(stats.peak(), 1);
+        assert_eq!(stats.queued(), 0);
+
+        let limiter = ConcurrencyLimiter::new(1)
+            .with_queue_timeout(Duration::from_secs(5))
+            .with_max_queued(1);
+        let stats = limiter.stats();
+        let service = Service::new(Router::with_hoop(limiter).get(slow));
+        let codes = send_all(&service, 3).await;
+        let ok = codes.iter().filter(|c| **c == Some(StatusCode::OK)).count();
+        assert_eq!(ok, 2);
+        assert_eq!(stats.shed(), 1);
+
+        let limiter = ConcurrencyLimiter::new(1).with_queue_timeout(Duration::from_millis(10));
+        let service = Service::new(Router::with_hoop(limiter).get(slow));
+        let codes = send_all(&service, 2).await;
+        assert!(codes.contains(&Some(StatusCode::SERVICE_UNAVAILABLE)));
+    }
+}
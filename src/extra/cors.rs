@@ -0,0 +1,492 @@
+use std::fmt::{self, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::add_vary;
+use crate::http::header::{
+    HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+};
+use crate::http::{Method, Request, Response, StatusCode, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+type OriginPredicate = Arc<dyn Fn(&HeaderValue, &Request) -> bool + Send + Sync>;
+
+/// Origins allowed to make cross-origin requests.
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum AllowOrigin {
+    Any,
+    Exact(HeaderValue),
+    List(Vec<HeaderValue>),
+    Predicate(OriginPredicate),
+}
+
+impl AllowOrigin {
+    pub fn any() -> Self {
+        AllowOrigin::Any
+    }
+    /// Panics if `origin` is not a valid header value.
+    pub fn exact(origin: &str) -> Self {
+        AllowOrigin::Exact(HeaderValue::from_str(origin).expect("invalid origin"))
+    }
+    /// Panics if any origin is not a valid header value.
+    pub fn list<I, O>(origins: I) -> Self
+    where
+        I: IntoIterator<Item = O>,
+        O: AsRef<str>,
+    {
+        AllowOrigin::List(
+            origins
+                .into_iter()
+                .map(|o| HeaderValue::from_str(o.as_ref()).expect("invalid origin"))
+                .collect(),
+        )
+    }
+    pub fn predicate<F>(f: F) -> Self
+    where
+        F: Fn(&HeaderValue, &Request) -> bool + Send + Sync + 'static,
+    {
+        AllowOrigin::Predicate(Arc::new(f))
+    }
+
+    fn is_allowed(&self, origin: &HeaderValue, req: &Request) -> bool {
+        match self {
+            AllowOrigin::Any => true,
+            AllowOrigin::Exact(allowed) => allowed == origin,
+            AllowOrigin::List(allowed) => allowed.contains(origin),
+            AllowOrigin::Predicate(f) => f(origin, req),
+        }
+    }
+}
+
+impl fmt::Debug for AllowOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AllowOrigin::Any => f.write_str("Any"),
+            AllowOrigin::Exact(origin) => f.debug_tuple("Exact").field(origin).finish(),
+            AllowOrigin::List(origins) => f.debug_tuple("List").field(origins).finish(),
+            AllowOrigin::Predicate(_) => f.write_str("Predicate"),
+        }
+    }
+}
+
+impl From<&str> for AllowOrigin {
+    fn from(origin: &str) -> Self {
+        if origin == "*" {
+            AllowOrigin::Any
+        } else {
+            AllowOrigin::exact(origin)
+        }
+    }
+}
+impl From<Vec<&str>> for AllowOrigin {
+    fn from(origins: Vec<&str>) -> Self {
+        AllowOrigin::list(origins)
+    }
+}
+impl<const N: usize> From<[&str; N]> for AllowOrigin {
+    fn from(origins: [&str; N]) -> Self {
+        AllowOrigin::list(origins)
+    }
+}
+
+/// Handles CORS requests, attach it with `Router::hoop`.
+///
+/// Preflight requests are answered by the hoop itself and the rest of the chain is skipped.
+/// A route without its own `OPTIONS` handler needs none: the `Service` answers `OPTIONS` for it
+/// only after running the hoops of its routers, this one included.
+#[derive(Clone, Debug)]
+pub struct Cors {
+    allow_origin: AllowOrigin,
+    allow_methods: Vec<Method>,
+    allow_headers: Option<Vec<HeaderName>>,
+    allow_credentials: bool,
+    expose_headers: Vec<HeaderName>,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cors {
+    /// No origin is allowed until configured with `with_allow_origin`.
+    pub fn new() -> Self {
+        Cors {
+            allow_origin: AllowOrigin::List(vec![]),
+            allow_methods: vec![
+                Method::GET,
+                Method::HEAD,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            allow_headers: Some(vec![]),
+            allow_credentials: false,
+            expose_headers: vec![],
+            max_age: None,
+        }
+    }
+    pub fn allow_origin(&self) -> &AllowOrigin {
+        &self.allow_origin
+    }
+    pub fn with_allow_origin(mut self, allow_origin: impl Into<AllowOrigin>) -> Self {
+        self.allow_origin = allow_origin.into();
+        self
+    }
+    pub fn allow_methods(&self) -> &Vec<Method> {
+        &self.allow_methods
+    }
+    pub fn with_allow_methods(mut self, methods: impl Into<Vec<Method>>) -> Self {
+        self.allow_methods = methods.into();
+        self
+    }
+    /// `None` means any requested header is allowed.
+    pub fn allow_headers(&self) -> Option<&Vec<HeaderName>> {
+        self.allow_headers.as_ref()
+    }
+    pub fn with_allow_headers(mut self, headers: impl Into<Vec<HeaderName>>) -> Self {
+        self.allow_headers = Some(headers.into());
+        self
+    }
+    pub fn with_allow_any_header(mut self) -> Self {
+        self.allow_headers = None;
+        self
+    }
+    pub fn allow_credentials(&self) -> bool {
+        self.allow_credentials
+    }
+    /// With credentials allowed the request origin is echoed back instead of `*`.
+    pub fn with_allow_credentials(mut self, allow_credentials: bool) -> Self {
+        self.allow_credentials = allow_credentials;
+        self
+    }
+    pub fn expose_headers(&self) -> &Vec<HeaderName> {
+        &self.expose_headers
+    }
+    pub fn with_expose_headers(mut self, headers: impl Into<Vec<HeaderName>>) -> Self {
+        self.expose_headers = headers.into();
+        self
+    }
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn is_wildcard(&self) -> bool {
+        matches!(self.allow_origin, AllowOrigin::Any) && !self.allow_credentials
+    }
+
+    fn allowed_origin(&self, req: &Request) -> Option<HeaderValue> {
+        let origin = req.headers().get(ORIGIN)?;
+        if !self.allow_origin.is_allowed(origin, req) {
+            None
+        } else if self.is_wildcard() {
+            Some(HeaderValue::from_static("*"))
+        } else {
+            Some(origin.clone())
+        }
+    }
+
+    fn is_method_allowed(&self, req: &Request) -> bool {
+        req.headers()
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|v| Method::from_bytes(v.as_bytes()).ok())
+            .map(|m| self.allow_methods.contains(&m))
+            .unwrap_or(false)
+    }
+
+    fn are_headers_allowed(&self, req: &Request) -> bool {
+        let allowed = match &self.allow_headers {
+            Some(allowed) => allowed,
+            None => return true,
+        };
+        req.headers()
+            .get_all(ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .all(|v| allowed.iter().any(|h| h.as_str().eq_ignore_ascii_case(v)))
+    }
+
+    fn write_preflight(&self, req: &Request, origin: HeaderValue, res: &mut Response) {
+        let headers = res.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.allow_credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Ok(methods) =
+            HeaderValue::from_str(&join(self.allow_methods.iter().map(Method::as_str)))
+        {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        let allow_headers = match &self.allow_headers {
+            Some(allowed) => {
+                HeaderValue::from_str(&join(allowed.iter().map(HeaderName::as_str))).ok()
+            }
+            None => req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+        };
+        if let Some(allow_headers) = allow_headers.filter(|v| !v.is_empty()) {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        if !self.is_wildcard() {
+            add_vary(res, ORIGIN);
+        }
+        add_vary(res, ACCESS_CONTROL_REQUEST_METHOD);
+        add_vary(res, ACCESS_CONTROL_REQUEST_HEADERS);
+        res.set_status_code(StatusCode::NO_CONTENT);
+    }
+
+    fn write_actual(&self, origin: Option<HeaderValue>, res: &mut Response) {
+        if !self.is_wildcard() {
+            add_vary(res, ORIGIN);
+        }
+        let origin = match origin {
+            Some(origin) => origin,
+            None => return,
+        };
+        let headers = res.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        if self.allow_credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if !self.expose_headers.is_empty() {
+            if let Ok(expose) =
+                HeaderValue::from_str(&join(self.expose_headers.iter().map(HeaderName::as_str)))
+            {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+            }
+        }
+    }
+}
+
+fn join<'a>(items: impl Iterator<Item = &'a str>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+#[async_trait]
+impl Handler for Cors {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let origin = self.allowed_origin(req);
+        let is_preflight = req.method() == Method::OPTIONS
+            && req.headers().contains_key(ORIGIN)
+            && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            match origin {
+                Some(origin) if self.is_method_allowed(req) && self.are_headers_allowed(req) => {
+                    self.write_preflight(req, origin, res);
+                    ctrl.skip_rest();
+                }
+                _ => {
+                    if !self.is_wildcard() {
+                        add_vary(res, ORIGIN);
+                    }
+                    res.set_status_error(
+                        StatusError::forbidden()
+                            .with_summary("cors preflight request is not allowed"),
+                    );
+                }
+            }
+            return;
+        }
+
+        ctrl.call_next(req, depot, res).await;
+        self.write_actual(origin, res);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::header::*;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    fn service(cors: Cors) -> Service {
+        Service::new(
+            Router::with_hoop(cors)
+                .path("hello")
+                .get(hello)
+                .options(empty_handler),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        let service = service(
+            Cors::new()
+                .with_allow_origin(["https://a.com", "https://b.com"])
+                .with_allow_methods(vec![Method::GET, Method::POST])
+                .with_allow_headers(vec![CONTENT_TYPE, AUTHORIZATION])
+                .with_allow_credentials(true)
+                .with_max_age(Duration::from_secs(600)),
+        );
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://b.com", true)
+            .add_header(ACCESS_CONTROL_REQUEST_METHOD, "POST", true)
+            .add_header(ACCESS_CONTROL_REQUEST_HEADERS, "content-type", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NO_CONTENT));
+        let headers = res.headers();
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "https://b.com");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type, authorization"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+        let vary = headers
+            .get_all(VARY)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vary,
+            [
+                "origin",
+                "access-control-request-method",
+                "access-control-request-headers"
+            ]
+        );
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://b.com", true)
+            .add_header(ACCESS_CONTROL_REQUEST_METHOD, "DELETE", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::FORBIDDEN));
+        assert!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://c.com", true)
+            .add_header(ACCESS_CONTROL_REQUEST_METHOD, "GET", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::FORBIDDEN));
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://a.com", true)
+            .add_header(ACCESS_CONTROL_REQUEST_METHOD, "GET", true)
+            .add_header(ACCESS_CONTROL_REQUEST_HEADERS, "x-custom", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn test_cors_without_options_route() {
+        let service = Service::new(
+            Router::with_hoop(Cors::new().with_allow_origin("https://a.com"))
+                .path("hello")
+                .get(hello),
+        );
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://a.com", true)
+            .add_header(ACCESS_CONTROL_REQUEST_METHOD, "GET", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NO_CONTENT));
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "https://a.com");
+        assert!(res.headers().get(ALLOW).is_none());
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NO_CONTENT));
+        assert_eq!(res.headers()[ALLOW], "GET, OPTIONS");
+
+        let res = TestClient::put("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://a.com", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::METHOD_NOT_ALLOWED));
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "https://a.com");
+    }
+
+    #[tokio::test]
+    async fn test_cors_actual_request() {
+        let service = service(
+            Cors::new()
+                .with_allow_origin(AllowOrigin::predicate(|origin, _| {
+                    origin.as_bytes().ends_with(b".example.com")
+                }))
+                .with_expose_headers(vec![HeaderName::from_static("x-total")]),
+        );
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://api.example.com", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+        assert_eq!(
+            res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://api.example.com"
+        );
+        assert_eq!(res.headers()[ACCESS_CONTROL_EXPOSE_HEADERS], "x-total");
+        assert_eq!(res.headers()[VARY], "origin");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://evil.com", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+        assert!(res.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        assert_eq!(res.headers()[VARY], "origin");
+    }
+
+    #[tokio::test]
+    async fn test_cors_any_origin() {
+        let service = service(Cors::new().with_allow_origin("*").with_allow_any_header());
+
+        let res = TestClient::get("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://a.com", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(res.headers().get(VARY).is_none());
+
+        let res = TestClient::options("http://127.0.0.1:7878/hello")
+            .add_header(ORIGIN, "https://a.com", true)
+            .add_header(ACCESS_CONTROL_REQUEST_METHOD, "PUT", true)
+            .add_header(ACCESS_CONTROL_REQUEST_HEADERS, "x-custom", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NO_CONTENT));
+        assert_eq!(res.headers()[ACCESS_CONTROL_ALLOW_HEADERS], "x-custom");
+    }
+}
@@ -0,0 +1,412 @@
+use std::marker::PhantomData;
+
+pub use jsonwebtoken;
+use jsonwebtoken::{decode, DecodingKey, TokenData, Validation};
+use serde::de::DeserializeOwned;
+
+use crate::http::header::{HeaderName, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use crate::http::{Request, Response, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Key of the decoded `TokenData` in the `Depot`.
+pub const JWT_AUTH_DATA_KEY: &str = "::salvo::jwt_auth::data";
+/// Key of the `JwtAuthState` in the `Depot`.
+pub const JWT_AUTH_STATE_KEY: &str = "::salvo::jwt_auth::state";
+/// Key of the raw token in the `Depot`.
+pub const JWT_AUTH_TOKEN_KEY: &str = "::salvo::jwt_auth::token";
+
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum JwtAuthState {
+    /// A valid token was found.
+    Authorized,
+    /// No token was found.
+    Unauthorized,
+    /// A token was found but it is invalid or expired.
+    Forbidden,
+}
+
+/// Access to the results of `JwtAuth`.
+pub trait JwtAuthDepotExt {
+    fn jwt_auth_token(&self) -> Option<&str>;
+    fn jwt_auth_data<C>(&self) -> Option<&TokenData<C>>
+    where
+        C: Send + Sync + 'static;
+    fn jwt_auth_state(&self) -> JwtAuthState;
+}
+
+impl JwtAuthDepotExt for Depot {
+    fn jwt_auth_token(&self) -> Option<&str> {
+        self.get::<String>(JWT_AUTH_TOKEN_KEY).map(|s| &**s)
+    }
+    fn jwt_auth_data<C>(&self) -> Option<&TokenData<C>>
+    where
+        C: Send + Sync + 'static,
+    {
+        self.get(JWT_AUTH_DATA_KEY)
+    }
+    fn jwt_auth_state(&self) -> JwtAuthState {
+        self.get(JWT_AUTH_STATE_KEY)
+            .cloned()
+            .unwrap_or(JwtAuthState::Unauthorized)
+    }
+}
+
+/// Extracts a token from the request.
+#[async_trait]
+pub trait JwtTokenFinder: Send + Sync + 'static {
+    async fn find_token(&self, req: &mut Request) -> Option<String>;
+}
+
+/// Finds `Bearer` tokens in the `Authorization` header, or in other headers.
+#[derive(Clone, Debug)]
+pub struct HeaderFinder {
+    header_names: Vec<HeaderName>,
+}
+
+impl Default for HeaderFinder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderFinder {
+    pub fn new() -> Self {
+        HeaderFinder {
+            header_names: vec![AUTHORIZATION],
+        }
+    }
+    pub fn with_header_names(mut self, header_names: impl Into<Vec<HeaderName>>) -> Self {
+        self.header_names = header_names.into();
+        self
+    }
+}
+
+#[async_trait]
+impl JwtTokenFinder for HeaderFinder {
+    async fn find_token(&self, req: &mut Request) -> Option<String> {
+        self.header_names.iter().find_map(|name| {
+            let value = req.headers().get(name)?.to_str().ok()?.trim();
+            let (scheme, token) = value.split_once(' ')?;
+            scheme
+                .eq_ignore_ascii_case("bearer")
+                .then(|| token.trim().to_owned())
+        })
+    }
+}
+
+/// Finds tokens in a query parameter.
+#[derive(Clone, Debug)]
+pub struct QueryFinder {
+    query_name: String,
+}
+
+impl QueryFinder {
+    pub fn new(query_name: impl Into<String>) -> Self {
+        QueryFinder {
+            query_name: query_name.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl JwtTokenFinder for QueryFinder {
+    async fn find_token(&self, req: &mut Request) -> Option<String> {
+        req.query(&self.query_name)
+    }
+}
+
+/// Finds tokens in a cookie.
+#[derive(Clone, Debug)]
+pub struct CookieFinder {
+    cookie_name: String,
+}
+
+impl CookieFinder {
+    pub fn new(cookie_name: impl Into<String>) -> Self {
+        CookieFinder {
+            cookie_name: cookie_name.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl JwtTokenFinder for CookieFinder {
+    async fn find_token(&self, req: &mut Request) -> Option<String> {
+        req.cookie(&self.cookie_name).map(|c| c.value().to_owned())
+    }
+}
+
+/// Validates JSON Web Tokens and puts the decoded claims `C` into the `Depot`.
+///
+/// The signing algorithm and the checked standard claims (`exp`, `nbf`, `aud`, `iss`, ...)
+/// are configured with `with_validation`, e.g. `Validation::new(Algorithm::RS256)` together
+/// with `DecodingKey::from_rsa_pem` for RSA signatures.
+pub struct JwtAuth<C> {
+    decoding_key: DecodingKey,
+    validation: Validation,
+    finders: Vec<Box<dyn JwtTokenFinder>>,
+    force_passed: bool,
+    _claims: PhantomData<fn() -> C>,
+}
+
+impl<C> JwtAuth<C>
+where
+    C: DeserializeOwned + Send + Sync + 'static,
+{
+    /// Uses HS256 and looks for a `Bearer` token in the `Authorization` header by default.
+    pub fn new(decoding_key: DecodingKey) -> Self {
+        JwtAuth {
+            decoding_key,
+            validation: Validation::default(),
+            finders: vec![Box::new(HeaderFinder::new())],
+            force_passed: false,
+            _claims: PhantomData,
+        }
+    }
+    pub fn validation(&self) -> &Validation {
+        &self.validation
+    }
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+    pub fn finders(&self) -> &Vec<Box<dyn JwtTokenFinder>> {
+        &self.finders
+    }
+    /// Finders are tried in order until one returns a token.
+    pub fn with_finders(mut self, finders: Vec<Box<dyn JwtTokenFinder>>) -> Self {
+        self.finders = finders;
+        self
+    }
+    pub fn force_passed(&self) -> bool {
+        self.force_passed
+    }
+    /// When set, failed requests are passed on with the failure recorded in `JwtAuthState`
+    /// instead of being answered with 401.
+    pub fn with_force_passed(mut self, force_passed: bool) -> Self {
+        self.force_passed = force_passed;
+        self
+    }
+
+    pub fn decode(&self, token: &str) -> Result<TokenData<C>, jsonwebtoken::errors::Error> {
+        decode::<C>(token, &self.decoding_key, &self.validation)
+    }
+
+    async fn find_token(&self, req: &mut Request) -> Option<String> {
+        for finder in &self.finders {
+            if let Some(token) = finder.find_token(req).await {
+                return Some(token);
+            }
+        }
+        None
+    }
+}
+
+fn unauthorized(res: &mut Response, challenge: String) {
+    if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+        res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+    res.set_status_error(StatusError::unauthorized());
+}
+
+#[async_trait]
+impl<C> Handler for JwtAuth<C>
+where
+    C: DeserializeOwned + Send + Sync + 'static,
+{
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let token = match self.find_token(req).await {
+            Some(token) => token,
+            None => {
+                depot.insert(JWT_AUTH_STATE_KEY, JwtAuthState::Unauthorized);
+                if self.force_passed {
+                    ctrl.call_next(req, depot, res).await;
+                } else {
+                    unauthorized(res, "Bearer".into());
+                    ctrl.skip_rest();
+                }
+                return;
+            }
+        };
+        match self.decode(&token) {
+            Ok(data) => {
+                depot.insert(JWT_AUTH_DATA_KEY, data);
+                depot.insert(JWT_AUTH_STATE_KEY, JwtAuthState::Authorized);
+                depot.insert(JWT_AUTH_TOKEN_KEY, token);
+                ctrl.call_next(req, depot, res).await;
+            }
+            Err(e) => {
+                tracing::info!(error = ?e, "jwt token is invalid");
+                depot.insert(JWT_AUTH_STATE_KEY, JwtAuthState::Forbidden);
+                if self.force_passed {
+                    ctrl.call_next(req, depot, res).await;
+                } else {
+                    let description = e.to_string().replace('"', "'");
+                    unauthorized(
+                        res,
+                        format!(
+                            "Bearer error=\"invalid_token\", error_description=\"{}\"",
+                            description
+                        ),
+                    );
+                    ctrl.skip_rest();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde::{Deserialize, Serialize};
+
+    use crate::http::header::COOKIE;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: u64,
+    }
+
+    const SECRET: &[u8] = b"secret";
+
+    fn claims(ttl: i64) -> Claims {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Claims {
+            sub: "alice".into(),
+            exp: (now + ttl) as u64,
+        }
+    }
+
+    #[handler(internal)]
+    async fn whoami(depot: &mut Depot) -> String {
+        match depot.jwt_auth_state() {
+            JwtAuthState::Authorized => depot.jwt_auth_data::<Claims>().unwrap().claims.sub.clone(),
+            state => format!("{:?}", state),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jwt_auth_hmac() {
+        let auth = JwtAuth::<Claims>::new(DecodingKey::from_secret(SECRET)).with_finders(vec![
+            Box::new(HeaderFinder::new()),
+            Box::new(QueryFinder::new("jwt_token")),
+            Box::new(CookieFinder::new("jwt_token")),
+        ]);
+        let service =
+            Service::new(Router::with_hoop(auth).push(Router::with_path("whoami").get(whoami)));
+        let token = encode(
+            &Header::default(),
+            &claims(3600),
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap();
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .bearer_auth(&token)
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "alice");
+
+        let mut res = TestClient::get(format!("http://127.0.0.1:7878/whoami?jwt_token={}", token))
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "alice");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .add_header(COOKIE, format!("jwt_token={}", token), true)
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "alice");
+
+        let res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(res.headers()[WWW_AUTHENTICATE], "Bearer");
+
+        let expired = encode(
+            &Header::default(),
+            &claims(-3600),
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap();
+        let res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .bearer_auth(expired)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::UNAUTHORIZED));
+        assert!(res.headers()[WWW_AUTHENTICATE]
+            .to_str()
+            .unwrap()
+            .starts_with("Bearer error=\"invalid_token\""));
+
+        let forged = encode(
+            &Header::default(),
+            &claims(3600),
+            &EncodingKey::from_secret(b"other"),
+        )
+        .unwrap();
+        let res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .bearer_auth(forged)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[tokio::test]
+    async fn test_jwt_auth_rsa() {
+        let auth = JwtAuth::<Claims>::new(
+            DecodingKey::from_rsa_pem(include_bytes!("../../certs/public.pem")).unwrap(),
+        )
+        .with_validation(Validation::new(Algorithm::RS256))
+        .with_force_passed(true);
+        let service =
+            Service::new(Router::with_hoop(auth).push(Router::with_path("whoami").get(whoami)));
+        let token = encode(
+            &Header::new(Algorithm::RS256),
+            &claims(3600),
+            &EncodingKey::from_rsa_pem(include_bytes!("../../certs/key.pem")).unwrap(),
+        )
+        .unwrap();
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .bearer_auth(token)
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "alice");
+
+        let hmac = encode(
+            &Header::default(),
+            &claims(3600),
+            &EncodingKey::from_secret(SECRET),
+        )
+        .unwrap();
+        let mut res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .bearer_auth(hmac)
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "Forbidden");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "Unauthorized");
+    }
+}
@@ -0,0 +1,295 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use cookie::time::format_description::{self, well_known::Rfc3339, FormatItem};
+use cookie::time::OffsetDateTime;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use super::basic_auth::USERNAME_KEY;
+use super::final_status;
+use super::request_id::RequestIdDepotExt;
+use crate::http::{Request, Response, StatusCode};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Time format of the Common Log Format, e.g. `10/Oct/2000:13:55:36 -0700`.
+static COMMON_TIME_FORMAT: Lazy<Vec<FormatItem<'static>>> = Lazy::new(|| {
+    format_description::parse_borrowed::<1>(
+        "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]",
+    )
+    .unwrap()
+});
+
+/// How `Logger` writes access logs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogFormat {
+    /// One line per request in the Common Log Format of Apache and nginx.
+    Common,
+    /// One JSON object per line.
+    Json,
+    /// An `info` tracing event with one field per value.
+    Tracing,
+}
+
+/// Writes an access log entry for every request once the rest of the flow has run.
+///
+/// Catchers run after all hoops, so the logged status is the one the response will be sent
+/// with: 404 when no handler set a status or a body, 200 when one only set a body. The size of
+/// bodies rendered later by catchers, or streamed, is not known and left out.
+pub struct Logger {
+    format: LogFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Logger {
+    /// Emits tracing events.
+    pub fn new() -> Self {
+        Logger {
+            format: LogFormat::Tracing,
+            writer: Mutex::new(Box::new(io::stdout())),
+        }
+    }
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Where `Common` and `Json` lines go, stdout by default.
+    pub fn with_writer<W>(mut self, writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        self.writer = Mutex::new(Box::new(writer));
+        self
+    }
+
+    fn write_line(&self, line: &str) {
+        let mut writer = self.writer.lock();
+        if let Err(e) = writeln!(writer, "{}", line).and_then(|_| writer.flush()) {
+            tracing::error!(error = ?e, "failed to write access log");
+        }
+    }
+}
+
+struct AccessRecord<'a> {
+    req: &'a Request,
+    depot: &'a Depot,
+    status: StatusCode,
+    size: Option<u64>,
+    latency: Duration,
+    time: OffsetDateTime,
+}
+
+impl AccessRecord<'_> {
+    fn remote_ip(&self) -> Option<String> {
+        self.req
+            .remote_addr()
+            .and_then(|addr| addr.ip())
+            .map(|ip| ip.to_string())
+    }
+    fn user(&self) -> Option<&str> {
+        self.depot.get::<String>(USERNAME_KEY).map(|s| &**s)
+    }
+    fn uri(&self) -> &str {
+        self.req
+            .uri()
+            .path_and_query()
+            .map(|p| p.as_str())
+            .unwrap_or("/")
+    }
+
+    fn to_common(&self) -> String {
+        let time = self.time.format(&*COMMON_TIME_FORMAT).unwrap_or_default();
+        format!(
+            "{} - {} [{}] \"{} {} {:?}\" {} {}",
+            self.remote_ip().as_deref().unwrap_or("-"),
+            self.user().unwrap_or("-"),
+            time,
+            self.req.method(),
+            self.uri(),
+            self.req.version(),
+            self.status.as_u16(),
+            self.size
+                .map(|size| size.to_string())
+                .as_deref()
+                .unwrap_or("-"),
+        )
+    }
+    fn to_json(&self) -> String {
+        serde_json::json!({
+            "time": self.time.format(&Rfc3339).ok(),
+            "remote_addr": self.remote_ip(),
+            "user": self.user(),
+            "request_id": self.depot.request_id(),
+            "method": self.req.method().as_str(),
+            "uri": self.uri(),
+            "route": self.req.matched_route(),
+            "version": format!("{:?}", self.req.version()),
+            "status": self.status.as_u16(),
+            "size": self.size,
+            "latency_ms": self.latency.as_secs_f64() * 1000.0,
+        })
+        .to_string()
+    }
+    fn trace(&self) {
+        tracing::info!(
+            remote_addr = self.remote_ip().as_deref(),
+            user = self.user(),
+            request_id = self.depot.request_id(),
+            method = %self.req.method(),
+            uri = self.uri(),
+            route = self.req.matched_route(),
+            version = ?self.req.version(),
+            status = self.status.as_u16(),
+            size = self.size,
+            latency = ?self.latency,
+            "request completed"
+        );
+    }
+}
+
+#[async_trait]
+impl Handler for Logger {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let time = OffsetDateTime::now_utc();
+        let started_at = Instant::now();
+        ctrl.call_next(req, depot, res).await;
+        let latency = started_at.elapsed();
+
+        let status = final_status(res);
+        let caught = res.body().is_none() && (status.is_client_error() || status.is_server_error());
+        let record = AccessRecord {
+            req,
+            depot,
+            status,
+            size: if caught { None } else { res.body().size() },
+            latency,
+            time,
+        };
+        match self.format {
+            LogFormat::Common => self.write_line(&record.to_common()),
+            LogFormat::Json => self.write_line(&record.to_json()),
+            LogFormat::Tracing => record.trace(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::prelude::*;
+    use crate::test::TestClient;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn take_lines(&self) -> Vec<String> {
+            let buf = std::mem::take(&mut *self.0.lock());
+            String::from_utf8(buf)
+                .unwrap()
+                .lines()
+                .map(ToOwned::to_owned)
+                .collect()
+        }
+    }
+
+    #[handler(internal)]
+    async fn user() -> &'static str {
+        "alice"
+    }
+    #[handler(internal)]
+    async fn nothing() {}
+
+    fn router(logger: Logger) -> Router {
+        Router::with_hoop(logger).push(
+            Router::with_path("users")
+                .push(Router::with_path("<id:num>").get(user))
+                .push(Router::with_path("nothing").get(nothing)),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_logger_common() {
+        let buf = SharedBuf::default();
+        let service = Service::new(router(
+            Logger::new()
+                .with_format(LogFormat::Common)
+                .with_writer(buf.clone()),
+        ));
+
+        TestClient::get("http://127.0.0.1:7878/users/5?x=1")
+            .send(&service)
+            .await;
+        TestClient::get("http://127.0.0.1:7878/users/nothing")
+            .send(&service)
+            .await;
+        let lines = buf.take_lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("- - - ["));
+        assert!(lines[0].ends_with("] \"GET /users/5?x=1 HTTP/1.1\" 200 5"));
+        assert!(lines[1].ends_with("\"GET /users/nothing HTTP/1.1\" 404 -"));
+    }
+
+    #[tokio::test]
+    async fn test_logger_json() {
+        let buf = SharedBuf::default();
+        let service = Service::new(router(
+            Logger::new()
+                .with_format(LogFormat::Json)
+                .with_writer(buf.clone()),
+        ));
+
+        TestClient::get("http://127.0.0.1:7878/users/5")
+            .send(&service)
+            .await;
+        TestClient::get("http://127.0.0.1:7878/users/nothing")
+            .send(&service)
+            .await;
+        let lines = buf.take_lines();
+        let record: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(record["method"], "GET");
+        assert_eq!(record["uri"], "/users/5");
+        assert_eq!(record["route"], "/users/<id:num>");
+        assert_eq!(record["status"], 200);
+        assert_eq!(record["size"], 5);
+        assert!(record["latency_ms"].as_f64().unwrap() >= 0.0);
+        let record: serde_json::Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(record["route"], "/users/nothing");
+        assert_eq!(record["status"], 404);
+        assert!(record["size"].is_null());
+
+        let res = TestClient::get("http://127.0.0.1:7878/users/5")
+            .send(router(Logger::new()))
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use super::final_status;
+use crate::http::header::{HeaderValue, CONTENT_TYPE};
+use crate::http::{Request, Response, StatusCode};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Default latency buckets in seconds, the same as the Prometheus client libraries use.
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Content type of the Prometheus text exposition format.
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct SeriesKey {
+    method: String,
+    route: String,
+    status: &'static str,
+}
+
+#[derive(Debug)]
+struct Series {
+    count: u64,
+    sum: f64,
+    buckets: Vec<u64>,
+}
+
+#[derive(Debug)]
+struct Registry {
+    buckets: Vec<f64>,
+    series: Mutex<BTreeMap<SeriesKey, Series>>,
+}
+
+impl Registry {
+    fn observe(&self, key: SeriesKey, seconds: f64) {
+        let mut series = self.series.lock();
+        let series = series.entry(key).or_insert_with(|| Series {
+            count: 0,
+            sum: 0.0,
+            buckets: vec![0; self.buckets.len()],
+        });
+        series.count += 1;
+        series.sum += seconds;
+        for (count, bound) in series.buckets.iter_mut().zip(&self.buckets) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let series = self.series.lock();
+        let mut out = String::new();
+        out.push_str("# HELP http_requests_total Total number of HTTP requests.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for (key, series) in series.iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{{}}} {}",
+                labels(key),
+                series.count
+            );
+        }
+        out.push_str("# HELP http_request_duration_seconds HTTP request latencies in seconds.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (key, series) in series.iter() {
+            let labels = labels(key);
+            for (count, bound) in series.buckets.iter().zip(&self.buckets) {
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, series.count
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{{}}} {}",
+                labels, series.sum
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{{}}} {}",
+                labels, series.count
+            );
+        }
+        out
+    }
+}
+
+fn labels(key: &SeriesKey) -> String {
+    format!(
+        "method=\"{}\",route=\"{}\",status=\"{}\"",
+        escape_label(&key.method),
+        escape_label(&key.route),
+        key.status
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() / 100 {
+        1 => "1xx",
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Counts requests and records their latency, labelled by method, matched route template and
+/// status class.
+///
+/// Route templates such as `/users/<id:num>` are used instead of the request path, so the
+/// number of series stays bounded. Serve the collected metrics with `exporter`.
+pub struct Metrics {
+    registry: Arc<Registry>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Uses `DEFAULT_BUCKETS`.
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS.to_vec())
+    }
+    /// Upper bounds of the latency histogram buckets in seconds.
+    pub fn with_buckets(mut buckets: Vec<f64>) -> Self {
+        buckets.retain(|b| b.is_finite());
+        buckets.sort_by(|a, b| a.total_cmp(b));
+        buckets.dedup();
+        Metrics {
+            registry: Arc::new(Registry {
+                buckets,
+                series: Mutex::new(BTreeMap::new()),
+            }),
+        }
+    }
+    /// A handler rendering the metrics in the Prometheus text format.
+    pub fn exporter(&self) -> MetricsExporter {
+        MetricsExporter {
+            registry: self.registry.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Handler for Metrics {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let started_at = Instant::now();
+        ctrl.call_next(req, depot, res).await;
+        // Automatic `OPTIONS` and 405 answers run hoops too, but have no route to label.
+        let route = match req.matched_route() {
+            Some(route) => route.to_owned(),
+            None => return,
+        };
+        let key = SeriesKey {
+            method: req.method().to_string(),
+            route,
+            status: status_class(final_status(res)),
+        };
+        self.registry
+            .observe(key, started_at.elapsed().as_secs_f64());
+    }
+}
+
+/// Renders what a `Metrics` hoop collected, created with `Metrics::exporter`.
+#[derive(Clone)]
+pub struct MetricsExporter {
+    registry: Arc<Registry>,
+}
+
+#[async_trait]
+impl Handler for MetricsExporter {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        res.headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT));
+        if let Err(e) = res.write_body(self.registry.render()) {
+            tracing::error!(error = ?e, "failed to write metrics");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn user() -> &'static str {
+        "alice"
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let metrics = Metrics::with_buckets(vec![10.0, 0.5]);
+        let exporter = metrics.exporter();
+        let router = Router::new()
+            .push(
+                Router::with_hoop(metrics).push(
+                    Router::with_path("users/<id:num>")
+                        .get(user)
+                        .push(Router::with_path("missing").get(empty_handler)),
+                ),
+            )
+            .push(Router::with_path("metrics").get(exporter));
+        let service = Service::new(router);
+
+        for path in ["/users/1", "/users/2", "/users/3/missing"] {
+            TestClient::get(format!("http://127.0.0.1:7878{}", path))
+                .send(&service)
+                .await;
+        }
+        TestClient::post("http://127.0.0.1:7878/users/1")
+            .send(&service)
+            .await;
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/metrics")
+            .send(&service)
+            .await;
+        assert_eq!(res.headers()[CONTENT_TYPE], TEXT_FORMAT);
+        let text = res.take_string().await.unwrap();
+        let labels = r#"method="GET",route="/users/<id:num>",status="2xx""#;
+        assert!(text.contains(&format!("http_requests_total{{{}}} 2\n", labels)));
+        assert!(text.contains(&format!(
+            "http_request_duration_seconds_bucket{{{},le=\"0.5\"}} 2\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "http_request_duration_seconds_count{{{}}} 2\n",
+            labels
+        )));
+        assert!(text.contains(
+            r#"http_requests_total{method="GET",route="/users/<id:num>/missing",status="2xx"} 1"#
+        ));
+        assert!(!text.contains("POST"));
+        assert!(!text.contains("/metrics"));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(status_class(StatusCode::NOT_FOUND), "4xx");
+    }
+}
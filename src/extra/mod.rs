@@ -0,0 +1,60 @@
+pub mod basic_auth;
+pub mod compression;
+pub mod concurrency_limiter;
+pub mod cors;
+pub mod jwt_auth;
+pub mod logger;
+pub mod metrics;
+pub mod proxy;
+pub mod rate_limiter;
+pub mod request_id;
+pub mod serve_static;
+pub mod session;
+pub mod sse;
+pub mod timeout;
+pub mod ws;
+
+pub use basic_auth::{BasicAuth, BasicAuthDepotExt, BasicAuthValidator};
+pub use compression::{Compression, CompressionAlgo};
+pub use concurrency_limiter::{ConcurrencyLimiter, ConcurrencyStats};
+pub use cors::{AllowOrigin, Cors};
+pub use jwt_auth::{JwtAuth, JwtAuthDepotExt, JwtAuthState};
+pub use logger::{LogFormat, Logger};
+pub use metrics::{Metrics, MetricsExporter};
+pub use proxy::{Proxy, UpstreamSelector};
+pub use rate_limiter::{QuotaGuard, RateIssuer, RateLimiter, RateStore};
+pub use request_id::{RequestId, RequestIdDepotExt};
+pub use serve_static::StaticDir;
+pub use session::{Session, SessionDepotExt, SessionHandler, SessionStore};
+pub use sse::{Sse, SseEvent, SseKeepAlive};
+pub use timeout::{Timeout, TimeoutDepotExt};
+pub use ws::{Message, WebSocket, WebSocketUpgrade};
+
+use crate::http::header::{HeaderName, HeaderValue, VARY};
+use crate::http::{Response, StatusCode};
+
+/// Appends `name` to the `Vary` header unless it is listed already or `Vary: *` is set.
+pub(crate) fn add_vary(res: &mut Response, name: HeaderName) {
+    let varied = res
+        .headers()
+        .get_all(VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| {
+            let v = v.trim();
+            v == "*" || v.eq_ignore_ascii_case(name.as_str())
+        });
+    if !varied {
+        res.headers_mut().append(VARY, HeaderValue::from(name));
+    }
+}
+
+/// The status the response will be sent with once the flow is done, before catchers run.
+pub(crate) fn final_status(res: &Response) -> StatusCode {
+    res.status_code().unwrap_or(if res.body().is_none() {
+        StatusCode::NOT_FOUND
+    } else {
+        StatusCode::OK
+    })
+}
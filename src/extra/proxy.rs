@@ -0,0 +1,523 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hyper::client::HttpConnector;
+use hyper::Client;
+use parking_lot::Mutex;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::http::header::{
+    HeaderMap, HeaderName, HeaderValue, CONNECTION, FORWARDED, HOST, PROXY_AUTHENTICATE,
+    PROXY_AUTHORIZATION, SET_COOKIE, TE, TRAILER, TRANSFER_ENCODING, UPGRADE, VARY,
+};
+use crate::http::{ReqBody, Request, Response, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Characters escaped when the remaining path is appended to the upstream URL.
+const PATH_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Picks the upstream URL a request is forwarded to.
+pub trait UpstreamSelector: Send + Sync + 'static {
+    /// `None` when no upstream is available.
+    fn select(&self, req: &Request) -> Option<&str>;
+}
+
+impl UpstreamSelector for String {
+    fn select(&self, _req: &Request) -> Option<&str> {
+        Some(self)
+    }
+}
+
+impl UpstreamSelector for &'static str {
+    fn select(&self, _req: &Request) -> Option<&str> {
+        Some(self)
+    }
+}
+
+/// Cycles through the upstreams in order.
+#[derive(Debug)]
+pub struct RoundRobinSelector {
+    upstreams: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl RoundRobinSelector {
+    pub fn new<I, U>(upstreams: I) -> Self
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<String>,
+    {
+        RoundRobinSelector {
+            upstreams: upstreams.into_iter().map(Into::into).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl UpstreamSelector for RoundRobinSelector {
+    fn select(&self, _req: &Request) -> Option<&str> {
+        if self.upstreams.is_empty() {
+            return None;
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.upstreams.len();
+        Some(&self.upstreams[index])
+    }
+}
+
+/// Picks an upstream at random.
+#[derive(Debug)]
+pub struct RandomSelector {
+    upstreams: Vec<String>,
+}
+
+impl RandomSelector {
+    pub fn new<I, U>(upstreams: I) -> Self
+    where
+        I: IntoIterator<Item = U>,
+        U: Into<String>,
+    {
+        RandomSelector {
+            upstreams: upstreams.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl UpstreamSelector for RandomSelector {
+    fn select(&self, _req: &Request) -> Option<&str> {
+        if self.upstreams.is_empty() {
+            return None;
+        }
+        let index = fastrand::usize(..self.upstreams.len());
+        Some(&self.upstreams[index])
+    }
+}
+
+/// Spreads requests in proportion to the weights of the upstreams, interleaving them
+/// evenly (smooth weighted round-robin).
+#[derive(Debug)]
+pub struct WeightedSelector {
+    upstreams: Vec<(String, i64)>,
+    current: Mutex<Vec<i64>>,
+}
+
+impl WeightedSelector {
+    /// Upstreams with a weight of 0 are never selected.
+    pub fn new<I, U>(upstreams: I) -> Self
+    where
+        I: IntoIterator<Item = (U, u32)>,
+        U: Into<String>,
+    {
+        let upstreams = upstreams
+            .into_iter()
+            .map(|(upstream, weight)| (upstream.into(), i64::from(weight)))
+            .collect::<Vec<_>>();
+        WeightedSelector {
+            current: Mutex::new(vec![0; upstreams.len()]),
+            upstreams,
+        }
+    }
+}
+
+impl UpstreamSelector for WeightedSelector {
+    fn select(&self, _req: &Request) -> Option<&str> {
+        let total = self.upstreams.iter().map(|(_, weight)| weight).sum::<i64>();
+        if total == 0 {
+            return None;
+        }
+        let mut current = self.current.lock();
+        let mut selected = 0;
+        for (index, (_, weight)) in self.upstreams.iter().enumerate() {
+            current[index] += weight;
+            if current[index] > current[selected] {
+                selected = index;
+            }
+        }
+        current[selected] -= total;
+        Some(&self.upstreams[selected].0)
+    }
+}
+
+/// Forwards requests to an upstream server and streams its response back.
+///
+/// The part of the path matched by a `<**rest>` wildcard is appended to the upstream URL, along
+/// with the query. Hop-by-hop headers are dropped in both directions, and `X-Forwarded-For`,
+/// `X-Forwarded-Host`, `X-Forwarded-Proto` and `Forwarded` are added to the request.
+/// Only `http` upstreams are supported.
+pub struct Proxy<U> {
+    selector: U,
+    client: Client<HttpConnector>,
+}
+
+impl<U: UpstreamSelector> Proxy<U> {
+    pub fn new(selector: U) -> Self {
+        Proxy {
+            selector,
+            client: Client::new(),
+        }
+    }
+    pub fn selector(&self) -> &U {
+        &self.selector
+    }
+    pub fn with_client(mut self, client: Client<HttpConnector>) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn build_request(
+        &self,
+        upstream: &str,
+        req: &mut Request,
+    ) -> Result<hyper::Request<ReqBody>, StatusError> {
+        let rest = req
+            .params()
+            .iter()
+            .find(|(name, _)| name.starts_with('*'))
+            .map(|(_, rest)| rest.as_str())
+            .unwrap_or_default();
+        let mut url = upstream.trim_end_matches('/').to_owned();
+        if !rest.is_empty() {
+            url.push('/');
+            url.extend(utf8_percent_encode(rest, PATH_ENCODE_SET));
+        }
+        if let Some(query) = req.uri().query() {
+            url.push('?');
+            url.push_str(query);
+        }
+
+        let mut headers = req.headers().clone();
+        strip_hop_by_hop(&mut headers);
+        let host = headers.remove(HOST);
+        add_forwarded(&mut headers, req, host.as_ref());
+
+        let mut builder = hyper::Request::builder()
+            .method(req.method().clone())
+            .uri(&url);
+        if let Some(builder_headers) = builder.headers_mut() {
+            *builder_headers = headers;
+        }
+        builder
+            .body(req.body_take().unwrap_or_else(ReqBody::empty))
+            .map_err(|e| {
+                tracing::error!(error = ?e, url = %url, "invalid upstream request");
+                StatusError::bad_gateway()
+            })
+    }
+}
+
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in [
+        CONNECTION,
+        PROXY_AUTHENTICATE,
+        PROXY_AUTHORIZATION,
+        TE,
+        TRAILER,
+        TRANSFER_ENCODING,
+        UPGRADE,
+        HeaderName::from_static("keep-alive"),
+        HeaderName::from_static("proxy-connection"),
+    ] {
+        headers.remove(name);
+    }
+}
+
+fn add_forwarded(headers: &mut HeaderMap, req: &Request, host: Option<&HeaderValue>) {
+    let client_ip = req
+        .remote_addr()
+        .and_then(|addr| addr.ip())
+        .map(|ip| ip.to_string());
+    let proto = req.scheme().as_str();
+    let host = host.and_then(|host| host.to_str().ok());
+
+    let mut forwarded = Vec::new();
+    if let Some(ip) = &client_ip {
+        let mut xff = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>();
+        xff.push(ip);
+        let xff = xff.join(", ");
+        append_header(
+            headers,
+            HeaderName::from_static("x-forwarded-for"),
+            &xff,
+            true,
+        );
+        if ip.contains(':') {
+            forwarded.push(format!("for=\"[{}]\"", ip));
+        } else {
+            forwarded.push(format!("for={}", ip));
+        }
+    }
+    if let Some(host) = host {
+        append_header(
+            headers,
+            HeaderName::from_static("x-forwarded-host"),
+            host,
+            true,
+        );
+        forwarded.push(format!("host=\"{}\"", host));
+    }
+    append_header(
+        headers,
+        HeaderName::from_static("x-forwarded-proto"),
+        proto,
+        true,
+    );
+    forwarded.push(format!("proto={}", proto));
+    append_header(headers, FORWARDED, &forwarded.join(";"), false);
+}
+
+/// Adds the upstream response headers to those set by hoops so far. Upstream values replace the
+/// ones of the same name, except `Set-Cookie` and `Vary` which add up.
+fn merge_headers(target: &mut HeaderMap, headers: &HeaderMap) {
+    for name in headers.keys() {
+        if name != SET_COOKIE && name != VARY {
+            target.remove(name);
+        }
+        for value in headers.get_all(name) {
+            target.append(name.clone(), value.clone());
+        }
+    }
+}
+
+fn append_header(headers: &mut HeaderMap, name: HeaderName, value: &str, overwrite: bool) {
+    if let Ok(value) = HeaderValue::from_str(value) {
+        if overwrite {
+            headers.insert(name, value);
+        } else {
+            headers.append(name, value);
+        }
+    }
+}
+
+#[async_trait]
+impl<U: UpstreamSelector> Handler for Proxy<U> {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let upstream = match self.selector.select(req) {
+            Some(upstream) => upstream,
+            None => {
+                res.set_status_error(StatusError::service_unavailable());
+                ctrl.skip_rest();
+                return;
+            }
+        };
+        let request = match self.build_request(upstream, req) {
+            Ok(request) => request,
+            Err(e) => {
+                res.set_status_error(e);
+                ctrl.skip_rest();
+                return;
+            }
+        };
+        match self.client.request(request).await {
+            Ok(response) => {
+                let (parts, body) = response.into_parts();
+                let mut headers = parts.headers;
+                strip_hop_by_hop(&mut headers);
+                res.set_status_code(parts.status);
+                merge_headers(res.headers_mut(), &headers);
+                if let Err(e) = res.streaming(body) {
+                    tracing::error!(error = ?e, "failed to stream upstream response");
+                }
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, upstream = %upstream, "upstream request failed");
+                res.set_status_error(StatusError::bad_gateway());
+                ctrl.skip_rest();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::uri::Scheme;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn echo(req: &mut Request) -> String {
+        let body = String::from_utf8(req.payload().await.unwrap().clone()).unwrap();
+        let header = |key: &str| {
+            req.headers()
+                .get_all(key)
+                .iter()
+                .map(|v| v.to_str().unwrap())
+                .collect::<Vec<_>>()
+                .join("|")
+        };
+        format!(
+            "{} {} xff={} xfh={} xfp={} fwd={} conn={} keep={} body={}",
+            req.method(),
+            req.uri(),
+            header("x-forwarded-for"),
+            header("x-forwarded-host"),
+            header("x-forwarded-proto"),
+            header("forwarded"),
+            header("connection"),
+            header("x-keep"),
+            body
+        )
+    }
+
+    #[handler(internal)]
+    async fn path_name(req: &mut Request) -> String {
+        req.param::<String>("name").unwrap()
+    }
+
+    fn spawn_server(router: Router) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0");
+        let addr = listener.local_addr();
+        tokio::spawn(Server::new(listener).serve(router));
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let upstream = spawn_server(Router::with_path("<**rest>").handle(echo));
+        let proxy = spawn_server(
+            Router::with_path("api/<**rest>").handle(Proxy::new(format!("{}/base", upstream))),
+        );
+
+        let client = reqwest::Client::new();
+        let text = client
+            .post(format!("{}/api/users/a%20b?page=2", proxy))
+            .header("connection", "x-drop")
+            .header("x-drop", "1")
+            .header("x-keep", "1")
+            .header("x-forwarded-for", "10.0.0.1")
+            .header("x-forwarded-for", "10.0.0.2")
+            .body("payload")
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let host = proxy.trim_start_matches("http://");
+        assert_eq!(
+            text,
+            format!(
+                "POST /base/users/a%20b?page=2 xff=10.0.0.1, 10.0.0.2, 127.0.0.1 xfh={host} xfp=http \
+                 fwd=for=127.0.0.1;host=\"{host}\";proto=http conn= keep=1 body=payload"
+            )
+        );
+
+        // The forwarded proto is the one of the connection, e.g. behind a `RustlsListener`.
+        let mut handler =
+            Service::new(Router::with_path("api/<**rest>").handle(Proxy::new(upstream.clone())))
+                .hyper_handle(None);
+        handler.scheme = Scheme::HTTPS;
+        let req = TestClient::get("http://local.host/api/tls").build();
+        let text = handler.handle(req).await.take_string().await.unwrap();
+        assert!(text.contains("xfp=https"), "{}", text);
+        assert!(text.contains("proto=https"), "{}", text);
+
+        // Headers set by hoops before the proxy survive, unless upstream sends the same name.
+        #[handler(internal)]
+        async fn secure(
+            req: &mut Request,
+            depot: &mut Depot,
+            res: &mut Response,
+            ctrl: &mut FlowCtrl,
+        ) {
+            let headers = res.headers_mut();
+            headers.insert("x-frame-options", HeaderValue::from_static("DENY"));
+            headers.insert(VARY, HeaderValue::from_static("origin"));
+            headers.insert("content-type", HeaderValue::from_static("application/json"));
+            ctrl.call_next(req, depot, res).await;
+        }
+        let service = Service::new(
+            Router::with_path("api/<**rest>")
+                .hoop(secure)
+                .handle(Proxy::new(upstream.clone())),
+        );
+        let mut res = TestClient::get("http://local.host/api/secure")
+            .send(&service)
+            .await;
+        assert_eq!(res.headers()["x-frame-options"], "DENY");
+        assert_eq!(res.headers()[VARY], "origin");
+        assert_eq!(res.headers()["content-type"], "text/plain; charset=utf-8");
+        assert_eq!(res.headers().get_all("content-type").iter().count(), 1);
+        assert!(res.take_string().await.unwrap().starts_with("GET /secure"));
+
+        let res = client
+            .get(format!(
+                "{}/api/missing",
+                spawn_server(
+                    Router::with_path("api/<**rest>").handle(Proxy::new("http://127.0.0.1:1")),
+                )
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_selectors() {
+        let a = spawn_server(Router::with_path("<name>").get(path_name));
+        let b = spawn_server(Router::with_path("<name>").get(path_name));
+        let router = Router::with_path("<**rest>").handle(Proxy::new(RoundRobinSelector::new([
+            format!("{}/a", a),
+            format!("{}/b", b),
+        ])));
+        let proxy = spawn_server(router);
+        let client = reqwest::Client::new();
+        let mut names = Vec::new();
+        for _ in 0..4 {
+            let text = client
+                .get(&proxy)
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            names.push(text);
+        }
+        assert_eq!(names, ["a", "b", "a", "b"]);
+
+        let req = Request::new();
+        let weighted = WeightedSelector::new([("a", 5), ("b", 1), ("c", 1), ("d", 0)]);
+        let picked = (0..7)
+            .map(|_| weighted.select(&req).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(picked, ["a", "a", "b", "a", "c", "a", "a"]);
+        assert!(WeightedSelector::new([("a", 0)]).select(&req).is_none());
+
+        let random = RandomSelector::new(["a", "b"]);
+        assert!((0..10).all(|_| matches!(random.select(&req), Some("a" | "b"))));
+        assert!(RandomSelector::new(Vec::<String>::new())
+            .select(&req)
+            .is_none());
+    }
+}
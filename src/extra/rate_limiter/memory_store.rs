@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::time::Instant;
+
+use super::{saturating_add, RateStore};
+use crate::async_trait;
+
+/// Expired entries are swept at most this often while updating.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Keeps the quota state of every client in process memory.
+///
+/// Clones share the same states.
+#[derive(Debug)]
+pub struct MemoryStore<S> {
+    inner: Arc<Mutex<Inner<S>>>,
+}
+
+#[derive(Debug)]
+struct Inner<S> {
+    entries: HashMap<String, (S, Instant)>,
+    swept_at: Instant,
+}
+
+impl<S> Clone for MemoryStore<S> {
+    fn clone(&self) -> Self {
+        MemoryStore {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S> Default for MemoryStore<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> MemoryStore<S> {
+    pub fn new() -> Self {
+        MemoryStore {
+            inner: Arc::new(Mutex::new(Inner {
+                entries: HashMap::new(),
+                swept_at: Instant::now(),
+            })),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().entries.is_empty()
+    }
+    /// Removes expired states.
+    pub fn cleanup(&self) {
+        let mut inner = self.inner.lock();
+        inner.sweep(Instant::now());
+    }
+}
+
+impl<S> Inner<S> {
+    fn sweep(&mut self, now: Instant) {
+        self.entries.retain(|_, (_, expires_at)| *expires_at > now);
+        self.swept_at = now;
+    }
+}
+
+#[async_trait]
+impl<S> RateStore for MemoryStore<S>
+where
+    S: Send + Sync + 'static,
+{
+    type State = S;
+
+    async fn update<F, R>(&self, key: &str, ttl: Duration, f: F) -> R
+    where
+        F: FnOnce(&mut Option<S>) -> R + Send,
+        R: Send,
+    {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+        if now - inner.swept_at >= SWEEP_INTERVAL {
+            inner.sweep(now);
+        }
+        let mut state = inner
+            .entries
+            .remove(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(state, _)| state);
+        let result = f(&mut state);
+        if let Some(state) = state {
+            // A ttl too long to represent never expires in practice.
+            inner
+                .entries
+                .insert(key.to_owned(), (state, saturating_add(now, ttl)));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = MemoryStore::<u32>::new();
+        let ttl = Duration::from_millis(50);
+        for expected in 1..=2 {
+            let count = store
+                .update("alice", ttl, |state| {
+                    *state.get_or_insert(0) += 1;
+                    state.unwrap()
+                })
+                .await;
+            assert_eq!(count, expected);
+        }
+        store.update("bob", ttl, |state| *state = None).await;
+        assert_eq!(store.len(), 1);
+
+        tokio::time::sleep(ttl).await;
+        let state = store.update("alice", ttl, |state| *state).await;
+        assert_eq!(state, None);
+        store.cleanup();
+        assert!(store.is_empty());
+
+        store
+            .update("carol", Duration::MAX, |state| *state = Some(1))
+            .await;
+        store.cleanup();
+        assert_eq!(store.len(), 1);
+    }
+}
@@ -0,0 +1,302 @@
+mod memory_store;
+mod quota;
+
+pub use memory_store::MemoryStore;
+pub use quota::{
+    FixedWindow, FixedWindowState, SlidingWindow, SlidingWindowState, TokenBucket, TokenBucketState,
+};
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::http::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use crate::http::{Request, Response, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Stands for "never" where adding a duration would overflow an `Instant`.
+const FAR_FUTURE: Duration = Duration::from_secs(86400 * 365 * 30);
+
+/// `instant + duration`, or `FAR_FUTURE` from `instant` when that overflows.
+fn saturating_add(instant: Instant, duration: Duration) -> Instant {
+    instant
+        .checked_add(duration)
+        .unwrap_or_else(|| instant + FAR_FUTURE)
+}
+
+/// Identifies the client a request is counted against.
+#[async_trait]
+pub trait RateIssuer: Send + Sync + 'static {
+    /// Requests without a key are not limited.
+    async fn issue(&self, req: &mut Request, depot: &Depot) -> Option<String>;
+}
+
+/// Keys requests by the IP of the remote address.
+///
+/// Behind a reverse proxy this is the address of the proxy, use a `HeaderIssuer` instead.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RemoteIpIssuer;
+
+#[async_trait]
+impl RateIssuer for RemoteIpIssuer {
+    async fn issue(&self, req: &mut Request, _depot: &Depot) -> Option<String> {
+        req.remote_addr()?.ip().map(|ip| ip.to_string())
+    }
+}
+
+/// Keys requests by the value of a header, e.g. `X-Forwarded-For` or an API key.
+#[derive(Clone, Debug)]
+pub struct HeaderIssuer {
+    name: HeaderName,
+}
+
+impl HeaderIssuer {
+    pub fn new(name: HeaderName) -> Self {
+        HeaderIssuer { name }
+    }
+}
+
+#[async_trait]
+impl RateIssuer for HeaderIssuer {
+    async fn issue(&self, req: &mut Request, _depot: &Depot) -> Option<String> {
+        req.headers()
+            .get(&self.name)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned)
+    }
+}
+
+/// Keys requests by a `String` an earlier hoop put into the `Depot`, e.g. the user
+/// authenticated by `BasicAuth` under `basic_auth::USERNAME_KEY`.
+#[derive(Clone, Debug)]
+pub struct DepotIssuer {
+    key: String,
+}
+
+impl DepotIssuer {
+    pub fn new(key: impl Into<String>) -> Self {
+        DepotIssuer { key: key.into() }
+    }
+}
+
+#[async_trait]
+impl RateIssuer for DepotIssuer {
+    async fn issue(&self, _req: &mut Request, depot: &Depot) -> Option<String> {
+        depot.get::<String>(&self.key).cloned()
+    }
+}
+
+/// Outcome of a `QuotaGuard` check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateStatus {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    /// Time until the quota is fully available again.
+    pub reset: Duration,
+    /// Time until the next request can be allowed, set when the request is rejected.
+    pub retry_after: Option<Duration>,
+}
+
+/// A rate limiting algorithm.
+pub trait QuotaGuard: Send + Sync + 'static {
+    /// Per client state of the algorithm.
+    type State: Send + Sync + 'static;
+
+    /// Counts a request made at `now` against `state`, `None` for a client without state.
+    fn check(&self, state: &mut Option<Self::State>, now: Instant) -> RateStatus;
+    /// How long the state of an idle client must be kept.
+    fn ttl(&self) -> Duration;
+}
+
+/// Keeps the `QuotaGuard` state of every client.
+#[async_trait]
+pub trait RateStore: Send + Sync + 'static {
+    type State: Send + Sync + 'static;
+
+    /// Runs `f` on the state of `key`, which expires once it has not been updated for `ttl`.
+    ///
+    /// Concurrent updates of one key must not interleave.
+    async fn update<F, R>(&self, key: &str, ttl: Duration, f: F) -> R
+    where
+        F: FnOnce(&mut Option<Self::State>) -> R + Send,
+        R: Send;
+}
+
+/// Answers clients going over their quota with `StatusError::too_many_requests()`.
+///
+/// Responses carry the `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers
+/// of the IETF draft, rejected ones also `Retry-After`.
+pub struct RateLimiter<I, G, S> {
+    issuer: I,
+    guard: G,
+    store: S,
+    add_headers: bool,
+}
+
+impl<I, G, S> RateLimiter<I, G, S>
+where
+    I: RateIssuer,
+    G: QuotaGuard,
+    S: RateStore<State = G::State>,
+{
+    pub fn new(issuer: I, guard: G, store: S) -> Self {
+        RateLimiter {
+            issuer,
+            guard,
+            store,
+            add_headers: true,
+        }
+    }
+    pub fn add_headers(&self) -> bool {
+        self.add_headers
+    }
+    pub fn with_add_headers(mut self, add_headers: bool) -> Self {
+        self.add_headers = add_headers;
+        self
+    }
+}
+
+fn as_secs_ceil(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+fn set_headers(res: &mut Response, status: &RateStatus) {
+    let headers = res.headers_mut();
+    headers.insert(
+        HeaderName::from_static("ratelimit-limit"),
+        status.limit.into(),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-remaining"),
+        status.remaining.into(),
+    );
+    headers.insert(
+        HeaderName::from_static("ratelimit-reset"),
+        as_secs_ceil(status.reset).into(),
+    );
+}
+
+#[async_trait]
+impl<I, G, S> Handler for RateLimiter<I, G, S>
+where
+    I: RateIssuer,
+    G: QuotaGuard,
+    S: RateStore<State = G::State>,
+{
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let key = match self.issuer.issue(req, depot).await {
+            Some(key) => key,
+            None => {
+                ctrl.call_next(req, depot, res).await;
+                return;
+            }
+        };
+        let now = Instant::now();
+        let status = self
+            .store
+            .update(&key, self.guard.ttl(), |state| self.guard.check(state, now))
+            .await;
+        if self.add_headers {
+            set_headers(res, &status);
+        }
+        if status.allowed {
+            ctrl.call_next(req, depot, res).await;
+        } else {
+            tracing::debug!(key = %key, "rate limit exceeded");
+            if let Some(retry_after) = status.retry_after {
+                res.headers_mut().insert(
+                    RETRY_AFTER,
+                    HeaderValue::from(as_secs_ceil(retry_after).max(1)),
+                );
+            }
+            res.set_status_error(StatusError::too_many_requests());
+            ctrl.skip_rest();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(
+            HeaderIssuer::new(HeaderName::from_static("x-api-key")),
+            FixedWindow::new(2, Duration::from_secs(60)),
+            MemoryStore::new(),
+        );
+        let service = Service::new(Router::with_hoop(limiter).get(hello));
+
+        for remaining in ["1", "0"] {
+            let mut res = TestClient::get("http://127.0.0.1:7878/")
+                .add_header("x-api-key", "alice", true)
+                .send(&service)
+                .await;
+            assert_eq!(res.headers()["ratelimit-limit"], "2");
+            assert_eq!(res.headers()["ratelimit-remaining"], remaining);
+            assert_eq!(res.headers()["ratelimit-reset"], "60");
+            assert_eq!(res.take_string().await.unwrap(), "hello");
+        }
+        let res = TestClient::get("http://127.0.0.1:7878/")
+            .add_header("x-api-key", "alice", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(res.headers()[RETRY_AFTER], "60");
+
+        let res = TestClient::get("http://127.0.0.1:7878/")
+            .add_header("x-api-key", "bob", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+
+        let res = TestClient::get("http://127.0.0.1:7878/")
+            .send(&service)
+            .await;
+        assert!(res.headers().get("ratelimit-limit").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_depot_issuer() {
+        #[handler(internal)]
+        async fn login(req: &mut Request, depot: &mut Depot) {
+            if let Some(user) = req.query::<String>("user") {
+                depot.insert("user", user);
+            }
+        }
+        let limiter = RateLimiter::new(
+            DepotIssuer::new("user"),
+            TokenBucket::new(1, Duration::from_secs(10)),
+            MemoryStore::new(),
+        )
+        .with_add_headers(false);
+        let service = Service::new(Router::with_hoop(login).hoop(limiter).get(hello));
+
+        let res = TestClient::get("http://127.0.0.1:7878/?user=alice")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+        assert!(res.headers().get("ratelimit-limit").is_none());
+        let res = TestClient::get("http://127.0.0.1:7878/?user=alice")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(res.headers()[RETRY_AFTER], "10");
+    }
+}
@@ -0,0 +1,326 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::{saturating_add, QuotaGuard, RateStatus};
+
+/// `duration * factor`, `Duration::MAX` when that does not fit.
+fn saturating_mul_f64(duration: Duration, factor: f64) -> Duration {
+    Duration::try_from_secs_f64(duration.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+}
+
+/// Allows `limit` requests in each consecutive `period`.
+///
+/// Cheap, but a client can make twice `limit` requests around the end of a window.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedWindow {
+    limit: u64,
+    period: Duration,
+}
+
+impl FixedWindow {
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(limit: u64, period: Duration) -> Self {
+        assert!(!period.is_zero(), "period must not be zero");
+        FixedWindow { limit, period }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FixedWindowState {
+    started_at: Instant,
+    count: u64,
+}
+
+impl QuotaGuard for FixedWindow {
+    type State = FixedWindowState;
+
+    fn check(&self, state: &mut Option<Self::State>, now: Instant) -> RateStatus {
+        let state = match state {
+            Some(state) if now < saturating_add(state.started_at, self.period) => state,
+            _ => state.insert(FixedWindowState {
+                started_at: now,
+                count: 0,
+            }),
+        };
+        let reset = saturating_add(state.started_at, self.period).saturating_duration_since(now);
+        let allowed = state.count < self.limit;
+        if allowed {
+            state.count += 1;
+        }
+        RateStatus {
+            allowed,
+            limit: self.limit,
+            remaining: self.limit - state.count,
+            reset,
+            retry_after: (!allowed).then_some(reset),
+        }
+    }
+    fn ttl(&self) -> Duration {
+        self.period
+    }
+}
+
+/// Allows `limit` requests in any `period`, estimated from the counts of the current and
+/// the previous fixed window.
+#[derive(Clone, Copy, Debug)]
+pub struct SlidingWindow {
+    limit: u64,
+    period: Duration,
+}
+
+impl SlidingWindow {
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(limit: u64, period: Duration) -> Self {
+        assert!(!period.is_zero(), "period must not be zero");
+        SlidingWindow { limit, period }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SlidingWindowState {
+    started_at: Instant,
+    current: u64,
+    previous: u64,
+}
+
+impl QuotaGuard for SlidingWindow {
+    type State = SlidingWindowState;
+
+    fn check(&self, state: &mut Option<Self::State>, now: Instant) -> RateStatus {
+        let state = state.get_or_insert(SlidingWindowState {
+            started_at: now,
+            current: 0,
+            previous: 0,
+        });
+        let elapsed = now - state.started_at;
+        if elapsed >= self.period {
+            let windows = elapsed.as_nanos() / self.period.as_nanos();
+            state.previous = if windows == 1 { state.current } else { 0 };
+            state.current = 0;
+            // So many windows have passed that realigning them on `now` makes no difference.
+            state.started_at = u32::try_from(windows)
+                .ok()
+                .and_then(|windows| self.period.checked_mul(windows))
+                .map_or(now, |passed| state.started_at + passed);
+        }
+        let window_left =
+            saturating_add(state.started_at, self.period).saturating_duration_since(now);
+        let previous_weight = window_left.as_secs_f64() / self.period.as_secs_f64();
+        let count = state.previous as f64 * previous_weight + state.current as f64;
+        let allowed = count + 1.0 <= self.limit as f64;
+        if allowed {
+            state.current += 1;
+        }
+        let count = (count + f64::from(u8::from(allowed))).ceil() as u64;
+        let retry_after = (!allowed).then(|| {
+            if state.current < self.limit && state.previous > 0 {
+                // Time until the previous window weighs little enough to make room for one request.
+                let room = (self.limit - state.current - 1) as f64 / state.previous as f64;
+                window_left.saturating_sub(saturating_mul_f64(self.period, room))
+            } else {
+                window_left
+            }
+        });
+        RateStatus {
+            allowed,
+            limit: self.limit,
+            remaining: self.limit.saturating_sub(count),
+            reset: if state.current > 0 {
+                window_left.saturating_add(self.period)
+            } else {
+                window_left
+            },
+            retry_after,
+        }
+    }
+    fn ttl(&self) -> Duration {
+        self.period.saturating_mul(2)
+    }
+}
+
+/// Allows bursts of up to `capacity` requests, refilled by one every `interval`.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenBucket {
+    capacity: u64,
+    interval: Duration,
+}
+
+impl TokenBucket {
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn new(capacity: u64, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "interval must not be zero");
+        TokenBucket { capacity, interval }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TokenBucketState {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl QuotaGuard for TokenBucket {
+    type State = TokenBucketState;
+
+    fn check(&self, state: &mut Option<Self::State>, now: Instant) -> RateStatus {
+        let capacity = self.capacity as f64;
+        let state = state.get_or_insert(TokenBucketState {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let refilled = (now - state.updated_at).as_secs_f64() / self.interval.as_secs_f64();
+        state.tokens = (state.tokens + refilled).min(capacity);
+        state.updated_at = now;
+        let allowed = state.tokens >= 1.0;
+        if allowed {
+            state.tokens -= 1.0;
+        }
+        RateStatus {
+            allowed,
+            limit: self.capacity,
+            remaining: state.tokens.floor() as u64,
+            reset: saturating_mul_f64(self.interval, capacity - state.tokens),
+            retry_after: (!allowed).then(|| saturating_mul_f64(self.interval, 1.0 - state.tokens)),
+        }
+    }
+    fn ttl(&self) -> Duration {
+        self.interval
+            .saturating_mul(u32::try_from(self.capacity).unwrap_or(u32::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_window() {
+        let guard = FixedWindow::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut state = None;
+        assert_eq!(guard.check(&mut state, start).remaining, 1);
+        assert_eq!(guard.check(&mut state, start).remaining, 0);
+        let status = guard.check(&mut state, start + Duration::from_secs(4));
+        assert!(!status.allowed);
+        assert_eq!(status.retry_after, Some(Duration::from_secs(6)));
+        let status = guard.check(&mut state, start + Duration::from_secs(10));
+        assert!(status.allowed);
+        assert_eq!(status.reset, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let guard = SlidingWindow::new(4, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut state = None;
+        for _ in 0..4 {
+            assert!(guard.check(&mut state, start).allowed);
+        }
+        assert!(!guard.check(&mut state, start).allowed);
+        // A quarter into the next window the previous one still weighs 3 requests.
+        let status = guard.check(&mut state, start + Duration::from_millis(12_500));
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 0);
+        let status = guard.check(&mut state, start + Duration::from_millis(12_500));
+        assert!(!status.allowed);
+        assert_eq!(status.retry_after, Some(Duration::from_millis(2_500)));
+        assert!(
+            guard
+                .check(&mut state, start + Duration::from_secs(15))
+                .allowed
+        );
+        // Two windows later nothing is left.
+        let status = guard.check(&mut state, start + Duration::from_secs(40));
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 3);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let guard = TokenBucket::new(2, Duration::from_secs(1));
+        let start = Instant::now();
+        let mut state = None;
+        assert!(guard.check(&mut state, start).allowed);
+        assert!(guard.check(&mut state, start).allowed);
+        let status = guard.check(&mut state, start + Duration::from_millis(250));
+        assert!(!status.allowed);
+        assert_eq!(status.retry_after, Some(Duration::from_millis(750)));
+        let status = guard.check(&mut state, start + Duration::from_secs(1));
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 0);
+        let status = guard.check(&mut state, start + Duration::from_secs(10));
+        assert!(status.allowed);
+        assert_eq!(status.remaining, 1);
+        assert_eq!(status.reset, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_large_quotas() {
+        let guard = TokenBucket::new(u64::MAX, Duration::from_secs(1));
+        assert_eq!(guard.ttl(), Duration::from_secs(u32::MAX as u64));
+        let guard = SlidingWindow::new(1, Duration::MAX);
+        assert_eq!(guard.ttl(), Duration::MAX);
+
+        let guard = SlidingWindow::new(1, Duration::from_nanos(1));
+        let start = Instant::now();
+        let mut state = None;
+        assert!(guard.check(&mut state, start).allowed);
+        let now = start + Duration::from_secs(5);
+        assert!(guard.check(&mut state, now).allowed);
+        assert_eq!(state.unwrap().started_at, now);
+
+        let guard = FixedWindow::new(1, Duration::MAX);
+        let mut state = None;
+        assert!(guard.check(&mut state, start).allowed);
+        let status = guard.check(&mut state, start + Duration::from_secs(3600));
+        assert!(!status.allowed);
+        assert!(status.retry_after.unwrap() > Duration::from_secs(86400 * 365));
+
+        let guard = SlidingWindow::new(1, Duration::MAX);
+        let mut state = None;
+        assert!(guard.check(&mut state, start).allowed);
+        let status = guard.check(&mut state, start);
+        assert!(!status.allowed);
+        assert_eq!(status.reset, Duration::MAX);
+
+        let guard = TokenBucket::new(1, Duration::MAX);
+        let mut state = None;
+        assert!(guard.check(&mut state, start).allowed);
+        let status = guard.check(&mut state, start);
+        assert_eq!(status.retry_after, Some(Duration::MAX));
+
+        let guard = TokenBucket::new(10_000_000_000_000_000, Duration::from_secs(3600));
+        let mut state = Some(TokenBucketState {
+            tokens: 0.0,
+            updated_at: start,
+        });
+        let status = guard.check(&mut state, start);
+        assert!(!status.allowed);
+        assert_eq!(status.reset, Duration::MAX);
+        assert_eq!(status.retry_after, Some(Duration::from_secs(3600)));
+        assert!(
+            TokenBucket::new(u64::MAX, Duration::from_secs(1))
+                .check(&mut None, start)
+                .allowed
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "period must not be zero")]
+    fn test_zero_period() {
+        SlidingWindow::new(1, Duration::ZERO);
+    }
+
+    #[test]
+    #[should_panic(expected = "interval must not be zero")]
+    fn test_zero_interval() {
+        TokenBucket::new(1, Duration::ZERO);
+    }
+}
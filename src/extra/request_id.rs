@@ -0,0 +1,170 @@
+use textnonce::TextNonce;
+use tracing::Instrument;
+
+use crate::http::header::{HeaderName, HeaderValue};
+use crate::http::{Request, Response};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Key of the request id in the `Depot`.
+pub const REQUEST_ID_KEY: &str = "::salvo::request_id";
+/// Incoming ids longer than this are replaced.
+const MAX_ID_LENGTH: usize = 128;
+
+/// Access to the id set by `RequestId`.
+pub trait RequestIdDepotExt {
+    fn request_id(&self) -> Option<&str>;
+}
+
+impl RequestIdDepotExt for Depot {
+    fn request_id(&self) -> Option<&str> {
+        self.get::<String>(REQUEST_ID_KEY).map(|s| &**s)
+    }
+}
+
+type IdGenerator = Box<dyn Fn() -> String + Send + Sync>;
+
+/// Gives every request an id, taken from the `X-Request-Id` header or generated.
+///
+/// The id is put into the `Depot` and the request headers, so a `Proxy` forwards it, echoed
+/// on the response, and recorded on a `request` tracing span around the rest of the flow.
+pub struct RequestId {
+    header_name: HeaderName,
+    trust_incoming: bool,
+    generator: IdGenerator,
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestId {
+    /// Generates ids with `TextNonce`.
+    pub fn new() -> Self {
+        RequestId {
+            header_name: HeaderName::from_static("x-request-id"),
+            trust_incoming: true,
+            generator: Box::new(|| TextNonce::sized_urlsafe(32).unwrap().into_string()),
+        }
+    }
+    pub fn header_name(&self) -> &HeaderName {
+        &self.header_name
+    }
+    pub fn with_header_name(mut self, header_name: HeaderName) -> Self {
+        self.header_name = header_name;
+        self
+    }
+    pub fn trust_incoming(&self) -> bool {
+        self.trust_incoming
+    }
+    /// When unset, ids sent by clients are ignored and a new one is always generated.
+    pub fn with_trust_incoming(mut self, trust_incoming: bool) -> Self {
+        self.trust_incoming = trust_incoming;
+        self
+    }
+    pub fn with_generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.generator = Box::new(generator);
+        self
+    }
+
+    fn incoming_id(&self, req: &Request) -> Option<String> {
+        if !self.trust_incoming {
+            return None;
+        }
+        let value = req.headers().get(&self.header_name)?.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_ID_LENGTH
+            && value.bytes().all(|b| b.is_ascii_graphic());
+        valid.then(|| value.to_owned())
+    }
+}
+
+#[async_trait]
+impl Handler for RequestId {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let id = self.incoming_id(req).unwrap_or_else(|| (self.generator)());
+        let value = match HeaderValue::from_str(&id) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::error!(error = ?e, "generated request id is not a valid header value");
+                ctrl.call_next(req, depot, res).await;
+                return;
+            }
+        };
+        req.headers_mut()
+            .insert(self.header_name.clone(), value.clone());
+        res.headers_mut()
+            .insert(self.header_name.clone(), value.clone());
+        depot.insert(REQUEST_ID_KEY, id.clone());
+
+        let span = tracing::info_span!("request", request_id = %id);
+        ctrl.call_next(req, depot, res).instrument(span).await;
+        if !res.headers().contains_key(&self.header_name) {
+            res.headers_mut().insert(self.header_name.clone(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn echo(req: &mut Request, depot: &mut Depot) -> String {
+        let id = depot.request_id().unwrap();
+        assert_eq!(req.headers()["x-request-id"], id);
+        id.to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let service = Service::new(Router::with_hoop(RequestId::new()).get(echo));
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/")
+            .add_header("x-request-id", "abc-123", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.headers()["x-request-id"], "abc-123");
+        assert_eq!(res.take_string().await.unwrap(), "abc-123");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/")
+            .send(&service)
+            .await;
+        let id = res.headers()["x-request-id"].to_str().unwrap().to_owned();
+        assert_eq!(id.len(), 32);
+        assert_eq!(res.take_string().await.unwrap(), id);
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/")
+            .add_header("x-request-id", "has space", true)
+            .send(&service)
+            .await;
+        assert_ne!(res.take_string().await.unwrap(), "has space");
+    }
+
+    #[tokio::test]
+    async fn test_request_id_custom() {
+        let request_id = RequestId::new()
+            .with_header_name(HeaderName::from_static("x-trace-id"))
+            .with_trust_incoming(false)
+            .with_generator(|| "fixed".into());
+        let router = Router::with_hoop(request_id).get(empty_handler);
+        let res = TestClient::get("http://127.0.0.1:7878/")
+            .add_header("x-trace-id", "client", true)
+            .send(router)
+            .await;
+        assert_eq!(res.headers()["x-trace-id"], "fixed");
+    }
+}
@@ -0,0 +1,427 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+
+use crate::fs::NamedFile;
+use crate::http::{guess_accept_mime, Request, Response, StatusCode, StatusError};
+use crate::writer::{Json, Redirect, Text};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+pub trait StaticRoots {
+    fn collect(self) -> Vec<PathBuf>;
+}
+impl StaticRoots for &str {
+    fn collect(self) -> Vec<PathBuf> {
+        vec![PathBuf::from(self)]
+    }
+}
+impl StaticRoots for &String {
+    fn collect(self) -> Vec<PathBuf> {
+        vec![PathBuf::from(self)]
+    }
+}
+impl StaticRoots for String {
+    fn collect(self) -> Vec<PathBuf> {
+        vec![PathBuf::from(self)]
+    }
+}
+impl StaticRoots for PathBuf {
+    fn collect(self) -> Vec<PathBuf> {
+        vec![self]
+    }
+}
+impl<T> StaticRoots for Vec<T>
+where
+    T: Into<PathBuf> + AsRef<std::ffi::OsStr>,
+{
+    fn collect(self) -> Vec<PathBuf> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+impl<T, const N: usize> StaticRoots for [T; N]
+where
+    T: Into<PathBuf> + AsRef<std::ffi::OsStr>,
+{
+    fn collect(self) -> Vec<PathBuf> {
+        self.into_iter().map(Into::into).collect()
+    }
+}
+
+/// Serves files under the given roots, it should be mounted with a rest wisp such as `<**path>`.
+///
+/// Roots are searched in order, the first one containing the requested path wins.
+pub struct StaticDir {
+    roots: Vec<PathBuf>,
+    dot_files: bool,
+    listing: bool,
+    defaults: Vec<String>,
+}
+
+impl StaticDir {
+    pub fn new(roots: impl StaticRoots) -> Self {
+        StaticDir {
+            roots: roots.collect(),
+            dot_files: false,
+            listing: false,
+            defaults: vec![],
+        }
+    }
+    pub fn roots(&self) -> &Vec<PathBuf> {
+        &self.roots
+    }
+    /// Allows serving and listing files whose name starts with `.`.
+    pub fn with_dot_files(mut self, dot_files: bool) -> Self {
+        self.dot_files = dot_files;
+        self
+    }
+    /// Renders the entries of a directory which has no default file.
+    pub fn with_listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+    /// Files such as `index.html` served when a directory is requested.
+    pub fn with_defaults<I, S>(mut self, defaults: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.defaults = defaults.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Joins the request path to `root`, returns `None` when it tries to escape from the root.
+    async fn sanitize(&self, root: &Path, rest: &str) -> Option<PathBuf> {
+        let mut path = root.to_path_buf();
+        for segment in rest.split('/') {
+            match segment {
+                "" | "." => continue,
+                ".." => return None,
+                _ if segment.contains('\\') || segment.contains('\0') => return None,
+                _ if segment.contains(':') && cfg!(windows) => return None,
+                _ if segment.starts_with('.') && !self.dot_files => return None,
+                _ => path.push(segment),
+            }
+        }
+        let canonical_root = tokio::fs::canonicalize(root).await.ok()?;
+        let canonical = tokio::fs::canonicalize(path).await.ok()?;
+        if canonical.starts_with(&canonical_root) {
+            Some(canonical)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct FileInfo {
+    name: String,
+    size: u64,
+    modified: Option<String>,
+}
+#[derive(Serialize, Debug)]
+struct DirInfo {
+    name: String,
+    modified: Option<String>,
+}
+#[derive(Serialize, Debug)]
+struct DirListing {
+    path: String,
+    dirs: Vec<DirInfo>,
+    files: Vec<FileInfo>,
+}
+
+impl DirListing {
+    async fn read(req_path: &str, dir: &Path, dot_files: bool) -> std::io::Result<DirListing> {
+        let mut dirs = vec![];
+        let mut files = vec![];
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') && !dot_files {
+                continue;
+            }
+            let metadata = match entry.metadata().await {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let modified = metadata.modified().ok().map(format_time);
+            if metadata.is_dir() {
+                dirs.push(DirInfo { name, modified });
+            } else {
+                files.push(FileInfo {
+                    name,
+                    size: metadata.len(),
+                    modified,
+                });
+            }
+        }
+        dirs.sort_by(|a, b| a.name.cmp(&b.name));
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(DirListing {
+            path: req_path.to_owned(),
+            dirs,
+            files,
+        })
+    }
+
+    fn html(&self) -> String {
+        let mut items = String::new();
+        if self.path != "/" {
+            items.push_str(r#"<tr><td><a href="../">../</a></td><td></td><td></td></tr>"#);
+        }
+        for dir in &self.dirs {
+            items.push_str(&format!(
+                r#"<tr><td><a href="./{}/">{}/</a></td><td></td><td>{}</td></tr>"#,
+                encode_url_path(&dir.name),
+                escape_html(&dir.name),
+                dir.modified.as_deref().unwrap_or_default()
+            ));
+        }
+        for file in &self.files {
+            items.push_str(&format!(
+                r#"<tr><td><a href="./{}">{}</a></td><td>{}</td><td>{}</td></tr>"#,
+                encode_url_path(&file.name),
+                escape_html(&file.name),
+                file.size,
+                file.modified.as_deref().unwrap_or_default()
+            ));
+        }
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width">
+    <title>Index of {0}</title>
+</head>
+<body>
+    <h1>Index of {0}</h1>
+    <table>
+        <tr><th>Name</th><th>Size</th><th>Last Modified</th></tr>
+        {1}
+    </table>
+</body>
+</html>"#,
+            escape_html(&self.path),
+            items
+        )
+    }
+
+    fn plain(&self) -> String {
+        let mut lines = vec![format!("Index of {}", self.path)];
+        lines.extend(self.dirs.iter().map(|dir| format!("{}/", dir.name)));
+        lines.extend(self.files.iter().map(|file| file.name.clone()));
+        lines.join("\n")
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    httpdate::fmt_http_date(time)
+}
+
+fn encode_url_path(path: &str) -> String {
+    utf8_percent_encode(path, PATH_SEGMENT).to_string()
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[async_trait]
+impl Handler for StaticDir {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        let rest = req
+            .params()
+            .iter()
+            .find(|(key, _)| key.starts_with('*'))
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default();
+
+        for root in &self.roots {
+            let path = match self.sanitize(root, &rest).await {
+                Some(path) => path,
+                None => continue,
+            };
+            let metadata = match tokio::fs::metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_file() {
+                match NamedFile::open(path).await {
+                    Ok(file) => file.send(req, res).await,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "open static file failed");
+                        res.set_status_error(StatusError::internal_server_error());
+                    }
+                }
+                return;
+            }
+            if !metadata.is_dir() {
+                continue;
+            }
+            if !req.uri().path().ends_with('/') {
+                let mut location = format!("{}/", req.uri().path());
+                if let Some(query) = req.uri().query() {
+                    location = format!("{}?{}", location, query);
+                }
+                res.render(Redirect::found(location));
+                return;
+            }
+            for default in &self.defaults {
+                let file_path = path.join(default);
+                let is_file = tokio::fs::metadata(&file_path)
+                    .await
+                    .is_ok_and(|metadata| metadata.is_file());
+                if is_file {
+                    if let Ok(file) = NamedFile::open(file_path).await {
+                        file.send(req, res).await;
+                        return;
+                    }
+                }
+            }
+            if self.listing {
+                let listing = match DirListing::read(req.uri().path(), &path, self.dot_files).await {
+                    Ok(listing) => listing,
+                    Err(e) => {
+                        tracing::error!(error = ?e, "read static dir failed");
+                        res.set_status_error(StatusError::internal_server_error());
+                        return;
+                    }
+                };
+                res.set_status_code(StatusCode::OK);
+                let format = guess_accept_mime(req, None);
+                match format.subtype().as_ref() {
+                    "json" => res.render(Json(listing)),
+                    "plain" => res.render(Text::Plain(listing.plain())),
+                    _ => res.render(Text::Html(listing.html())),
+                }
+                return;
+            }
+            res.set_status_error(StatusError::forbidden());
+            return;
+        }
+        res.set_status_error(StatusError::not_found());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::header::*;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    fn setup() -> (tempfile::TempDir, Router) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("public");
+        std::fs::create_dir_all(root.join("docs/guide")).unwrap();
+        std::fs::write(dir.path().join("outside.txt"), "outside").unwrap();
+        std::fs::write(root.join("hello.txt"), "hello").unwrap();
+        std::fs::write(root.join(".secret"), "secret").unwrap();
+        std::fs::write(root.join("docs/index.html"), "<h1>docs</h1>").unwrap();
+        std::fs::write(root.join("docs/guide/a b.md"), "guide").unwrap();
+        let router = Router::with_path("static/<**path>").get(
+            StaticDir::new(root)
+                .with_listing(true)
+                .with_defaults(["index.html"]),
+        );
+        (dir, router)
+    }
+
+    #[tokio::test]
+    async fn test_serve_file() {
+        let (_dir, router) = setup();
+        let service = Service::new(router);
+        let mut res = TestClient::get("http://127.0.0.1:7878/static/hello.txt")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/static/docs/guide/a%20b.md")
+            .add_header(RANGE, "bytes=1-", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::PARTIAL_CONTENT));
+        assert_eq!(res.take_string().await.unwrap(), "uide");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/static/docs/")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "<h1>docs</h1>");
+    }
+
+    #[tokio::test]
+    async fn test_serve_forbidden_paths() {
+        let (_dir, router) = setup();
+        let service = Service::new(router);
+        for path in [
+            "static/.secret",
+            "static/../outside.txt",
+            "static/docs/%2e%2e/%2e%2e/outside.txt",
+            "static/docs%2F..%2F..%2Foutside.txt",
+            "static/missing.txt",
+        ] {
+            let res = TestClient::get(format!("http://127.0.0.1:7878/{}", path))
+                .send(&service)
+                .await;
+            assert_eq!(res.status_code(), Some(StatusCode::NOT_FOUND), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serve_listing() {
+        let (_dir, router) = setup();
+        let service = Service::new(router);
+        let res = TestClient::get("http://127.0.0.1:7878/static/docs?a=1")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::FOUND));
+        assert_eq!(res.headers().get(LOCATION).unwrap(), "/static/docs/?a=1");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/static/")
+            .add_header(ACCEPT, "application/json", true)
+            .send(&service)
+            .await;
+        let content = res.take_string().await.unwrap();
+        assert!(content.contains(r#""name":"docs""#));
+        assert!(content.contains(r#""name":"hello.txt","size":5"#));
+        assert!(!content.contains("secret"));
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/static/docs/guide/")
+            .send(&service)
+            .await;
+        let content = res.take_string().await.unwrap();
+        assert!(content.contains(r#"<a href="./a%20b.md">a b.md</a>"#));
+    }
+}
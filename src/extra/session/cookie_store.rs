@@ -0,0 +1,58 @@
+use cookie::{Cookie, CookieJar, Key};
+
+use super::{Session, SessionStore};
+use crate::{async_trait, Error};
+
+const COOKIE_NAME: &str = "session";
+const MAX_COOKIE_LENGTH: usize = 4096;
+
+/// Keeps the whole session in the cookie, encrypted and authenticated with `key`.
+///
+/// Nothing is kept on the server, so destroyed or rotated sessions cannot be revoked
+/// before they expire.
+#[derive(Clone)]
+pub struct CookieStore {
+    key: Key,
+}
+
+impl CookieStore {
+    pub fn new(key: Key) -> Self {
+        CookieStore { key }
+    }
+}
+
+impl std::fmt::Debug for CookieStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieStore").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl SessionStore for CookieStore {
+    async fn load(&self, cookie_value: &str) -> crate::Result<Option<Session>> {
+        let mut jar = CookieJar::new();
+        jar.add_original(Cookie::new(COOKIE_NAME, cookie_value.to_owned()));
+        match jar.private(&self.key).get(COOKIE_NAME) {
+            Some(cookie) => Ok(serde_json::from_str(cookie.value()).ok()),
+            None => Ok(None),
+        }
+    }
+    async fn store(&self, session: &Session) -> crate::Result<String> {
+        let mut jar = CookieJar::new();
+        jar.private_mut(&self.key)
+            .add(Cookie::new(COOKIE_NAME, serde_json::to_string(session)?));
+        let value = jar
+            .get(COOKIE_NAME)
+            .map(|c| c.value().to_owned())
+            .unwrap_or_default();
+        if value.len() > MAX_COOKIE_LENGTH {
+            return Err(Error::other(
+                "session is too large to be stored in a cookie",
+            ));
+        }
+        Ok(value)
+    }
+    async fn destroy(&self, _id: &str) -> crate::Result<()> {
+        Ok(())
+    }
+}
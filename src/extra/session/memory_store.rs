@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use super::{Session, SessionStore};
+use crate::async_trait;
+
+/// Keeps sessions in process memory, the session cookie only carries the id.
+///
+/// Clones share the same sessions.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore {
+    inner: Arc<RwLock<HashMap<String, Session>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.inner.read().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().is_empty()
+    }
+    /// Removes expired sessions.
+    pub fn cleanup(&self) {
+        self.inner
+            .write()
+            .retain(|_, session| !session.is_expired());
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, cookie_value: &str) -> crate::Result<Option<Session>> {
+        Ok(self.inner.read().get(cookie_value).cloned())
+    }
+    async fn store(&self, session: &Session) -> crate::Result<String> {
+        let mut session = session.clone();
+        session.changed = false;
+        let id = session.id.clone();
+        self.inner.write().insert(id.clone(), session);
+        Ok(id)
+    }
+    async fn destroy(&self, id: &str) -> crate::Result<()> {
+        self.inner.write().remove(id);
+        Ok(())
+    }
+}
@@ -0,0 +1,441 @@
+mod cookie_store;
+mod memory_store;
+
+pub use cookie_store::CookieStore;
+pub use memory_store::MemoryStore;
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cookie::{Cookie, SameSite};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use textnonce::TextNonce;
+
+use crate::http::{Request, Response};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Key of the `Session` in the `Depot`.
+pub const SESSION_KEY: &str = "::salvo::session";
+const DEFAULT_COOKIE_NAME: &str = "salvo.session.id";
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Loads, saves and deletes sessions.
+///
+/// The value stored in the session cookie is opaque to `SessionHandler`: it is
+/// whatever `store` returns, a session id or the whole session data.
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    /// Loads the session referred to by the cookie value, `None` if it does not exist.
+    async fn load(&self, cookie_value: &str) -> crate::Result<Option<Session>>;
+    /// Saves the session and returns the value to send in the session cookie.
+    async fn store(&self, session: &Session) -> crate::Result<String>;
+    /// Deletes the session with `id`.
+    async fn destroy(&self, id: &str) -> crate::Result<()>;
+}
+
+/// Data shared between requests of one client.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Session {
+    id: String,
+    data: HashMap<String, Value>,
+    expires_at: Option<u64>,
+    #[serde(skip)]
+    changed: bool,
+    #[serde(skip)]
+    destroyed: bool,
+    #[serde(skip)]
+    replaced_id: Option<String>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            id: generate_id(),
+            data: HashMap::new(),
+            expires_at: None,
+            changed: false,
+            destroyed: false,
+            replaced_id: None,
+        }
+    }
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.data
+            .get(key)
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+    }
+    pub fn insert<T: Serialize>(&mut self, key: impl Into<String>, value: T) -> crate::Result<()> {
+        let value = serde_json::to_value(value)?;
+        let key = key.into();
+        if self.data.get(&key) != Some(&value) {
+            self.data.insert(key, value);
+            self.changed = true;
+        }
+        Ok(())
+    }
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let value = self.data.remove(key);
+        if value.is_some() {
+            self.changed = true;
+        }
+        value
+    }
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.data.keys()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    pub fn clear(&mut self) {
+        if !self.data.is_empty() {
+            self.data.clear();
+            self.changed = true;
+        }
+    }
+
+    /// Gives the session a new id while keeping its data, e.g. after login to prevent session fixation.
+    pub fn regenerate(&mut self) {
+        let old_id = std::mem::replace(&mut self.id, generate_id());
+        if self.replaced_id.is_none() {
+            self.replaced_id = Some(old_id);
+        }
+        self.changed = true;
+    }
+    /// Deletes the session from the store and the client once the request is handled.
+    pub fn destroy(&mut self) {
+        self.destroyed = true;
+    }
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed
+    }
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+    pub fn set_expiry(&mut self, ttl: Duration) {
+        self.expires_at = Some(unix_secs(SystemTime::now() + ttl));
+        self.changed = true;
+    }
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= unix_secs(SystemTime::now()),
+            None => false,
+        }
+    }
+}
+
+fn generate_id() -> String {
+    TextNonce::sized_urlsafe(32)
+        .expect("32 is a valid nonce length")
+        .into_string()
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Access to the `Session` loaded by `SessionHandler`.
+pub trait SessionDepotExt {
+    fn session(&self) -> Option<&Session>;
+    fn session_mut(&mut self) -> Option<&mut Session>;
+}
+
+impl SessionDepotExt for Depot {
+    fn session(&self) -> Option<&Session> {
+        self.get(SESSION_KEY)
+    }
+    fn session_mut(&mut self) -> Option<&mut Session> {
+        self.get_mut(SESSION_KEY)
+    }
+}
+
+/// Loads the `Session` into the `Depot` before the rest of the chain and saves it afterwards.
+pub struct SessionHandler<S> {
+    store: S,
+    cookie_name: String,
+    cookie_path: String,
+    cookie_domain: Option<String>,
+    secure: bool,
+    same_site: SameSite,
+    ttl: Option<Duration>,
+}
+
+impl<S: SessionStore> SessionHandler<S> {
+    pub fn new(store: S) -> Self {
+        SessionHandler {
+            store,
+            cookie_name: DEFAULT_COOKIE_NAME.into(),
+            cookie_path: "/".into(),
+            cookie_domain: None,
+            secure: true,
+            same_site: SameSite::Lax,
+            ttl: Some(DEFAULT_TTL),
+        }
+    }
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+    pub fn with_cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+    pub fn with_cookie_path(mut self, path: impl Into<String>) -> Self {
+        self.cookie_path = path.into();
+        self
+    }
+    pub fn with_cookie_domain(mut self, domain: impl Into<String>) -> Self {
+        self.cookie_domain = Some(domain.into());
+        self
+    }
+    pub fn with_secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+    /// Lifetime of new sessions, `None` makes them last until the browser is closed.
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn build_cookie(&self, value: String) -> Cookie<'static> {
+        let mut cookie = Cookie::build(self.cookie_name.clone(), value)
+            .path(self.cookie_path.clone())
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .finish();
+        if let Some(domain) = &self.cookie_domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+
+    async fn load_session(&self, req: &Request) -> Session {
+        let value = match req.cookie(&self.cookie_name) {
+            Some(cookie) => cookie.value().to_owned(),
+            None => return self.new_session(),
+        };
+        match self.store.load(&value).await {
+            Ok(Some(session)) if !session.is_expired() => session,
+            Ok(Some(session)) => {
+                if let Err(e) = self.store.destroy(session.id()).await {
+                    tracing::error!(error = ?e, "failed to destroy expired session");
+                }
+                self.new_session()
+            }
+            Ok(None) => self.new_session(),
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to load session");
+                self.new_session()
+            }
+        }
+    }
+
+    fn new_session(&self) -> Session {
+        let mut session = Session::new();
+        if let Some(ttl) = self.ttl {
+            session.expires_at = Some(unix_secs(SystemTime::now() + ttl));
+        }
+        session
+    }
+
+    async fn save_session(&self, mut session: Session, res: &mut Response) {
+        if let Some(old_id) = session.replaced_id.take() {
+            if let Err(e) = self.store.destroy(&old_id).await {
+                tracing::error!(error = ?e, "failed to destroy replaced session");
+            }
+        }
+        if session.is_destroyed() {
+            if let Err(e) = self.store.destroy(session.id()).await {
+                tracing::error!(error = ?e, "failed to destroy session");
+            }
+            let mut cookie = self.build_cookie(String::new());
+            cookie.make_removal();
+            res.add_cookie(cookie);
+            return;
+        }
+        if !session.is_changed() {
+            return;
+        }
+        match self.store.store(&session).await {
+            Ok(value) => {
+                let mut cookie = self.build_cookie(value);
+                if let Some(expires_at) = session.expires_at {
+                    let expires_at =
+                        cookie::time::OffsetDateTime::from_unix_timestamp(expires_at as i64)
+                            .unwrap_or(cookie::time::OffsetDateTime::UNIX_EPOCH);
+                    cookie.set_expires(expires_at);
+                }
+                res.add_cookie(cookie);
+            }
+            Err(e) => tracing::error!(error = ?e, "failed to store session"),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: SessionStore> Handler for SessionHandler<S> {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let session = self.load_session(req).await;
+        depot.insert(SESSION_KEY, session);
+
+        ctrl.call_next(req, depot, res).await;
+
+        if let Some(session) = depot.remove::<Session>(SESSION_KEY) {
+            self.save_session(session, res).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::header::{COOKIE, SET_COOKIE};
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn login(depot: &mut Depot) {
+        let session = depot.session_mut().unwrap();
+        session.regenerate();
+        session.insert("user", "alice").unwrap();
+    }
+    #[handler(internal)]
+    async fn whoami(depot: &mut Depot) -> String {
+        depot
+            .session()
+            .and_then(|s| s.get::<String>("user"))
+            .unwrap_or_else(|| "anonymous".into())
+    }
+    #[handler(internal)]
+    async fn logout(depot: &mut Depot) {
+        depot.session_mut().unwrap().destroy();
+    }
+
+    fn router<S: SessionStore>(handler: SessionHandler<S>) -> Router {
+        Router::with_hoop(handler)
+            .push(Router::with_path("login").post(login))
+            .push(Router::with_path("whoami").get(whoami))
+            .push(Router::with_path("logout").post(logout))
+    }
+
+    fn session_cookie(res: &Response) -> Option<Cookie<'static>> {
+        res.headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|v| Cookie::parse_encoded(v.to_str().unwrap().to_owned()).ok())
+            .find(|c| c.name() == DEFAULT_COOKIE_NAME)
+    }
+
+    async fn whoami_with(service: &Service, cookie: &Cookie<'_>) -> String {
+        TestClient::get("http://127.0.0.1:7878/whoami")
+            .add_header(COOKIE, cookie.encoded().to_string(), true)
+            .send(service)
+            .await
+            .take_string()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_memory_session() {
+        let store = MemoryStore::new();
+        let service = Service::new(router(SessionHandler::new(store.clone())));
+
+        let res = TestClient::get("http://127.0.0.1:7878/whoami")
+            .send(&service)
+            .await;
+        assert!(session_cookie(&res).is_none());
+
+        let res = TestClient::post("http://127.0.0.1:7878/login")
+            .send(&service)
+            .await;
+        let cookie = session_cookie(&res).unwrap();
+        assert!(cookie.http_only().unwrap());
+        assert!(cookie.expires().is_some());
+        assert_eq!(store.len(), 1);
+        assert_eq!(whoami_with(&service, &cookie).await, "alice");
+
+        // logging in again rotates the id and drops the old session
+        let res = TestClient::post("http://127.0.0.1:7878/login")
+            .add_header(COOKIE, cookie.encoded().to_string(), true)
+            .send(&service)
+            .await;
+        let rotated = session_cookie(&res).unwrap();
+        assert_ne!(rotated.value(), cookie.value());
+        assert_eq!(store.len(), 1);
+        assert_eq!(whoami_with(&service, &cookie).await, "anonymous");
+        assert_eq!(whoami_with(&service, &rotated).await, "alice");
+
+        let res = TestClient::post("http://127.0.0.1:7878/logout")
+            .add_header(COOKIE, rotated.encoded().to_string(), true)
+            .send(&service)
+            .await;
+        assert_eq!(session_cookie(&res).unwrap().value(), "");
+        assert!(store.is_empty());
+        assert_eq!(whoami_with(&service, &rotated).await, "anonymous");
+    }
+
+    #[tokio::test]
+    async fn test_cookie_session() {
+        let service = Service::new(router(SessionHandler::new(CookieStore::new(
+            cookie::Key::generate(),
+        ))));
+
+        let res = TestClient::post("http://127.0.0.1:7878/login")
+            .send(&service)
+            .await;
+        let cookie = session_cookie(&res).unwrap();
+        assert!(!cookie.value().contains("alice"));
+        assert_eq!(whoami_with(&service, &cookie).await, "alice");
+
+        let mut tampered = cookie.clone();
+        tampered.set_value(format!("{}x", cookie.value()));
+        assert_eq!(whoami_with(&service, &tampered).await, "anonymous");
+    }
+
+    #[tokio::test]
+    async fn test_session_expiry() {
+        let store = MemoryStore::new();
+        let mut session = Session::new();
+        session.insert("user", "alice").unwrap();
+        session.expires_at = Some(unix_secs(SystemTime::now()) - 1);
+        assert!(session.is_expired());
+        let value = store.store(&session).await.unwrap();
+
+        let service = Service::new(router(SessionHandler::new(store.clone())));
+        let cookie = Cookie::new(DEFAULT_COOKIE_NAME, value);
+        assert_eq!(whoami_with(&service, &cookie).await, "anonymous");
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter, Write};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_util::{Stream, TryStreamExt};
+use serde::Serialize;
+use tokio::time::{self, Instant, Sleep};
+
+use crate::http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use crate::http::{Request, Response};
+use crate::{BoxedError, Piece};
+
+const DEFAULT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Value of the `Last-Event-ID` header sent by a reconnecting client.
+pub fn last_event_id(req: &Request) -> Option<&str> {
+    req.headers()
+        .get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+}
+
+/// A server-sent event, see <https://html.spec.whatwg.org/multipage/server-sent-events.html>.
+#[derive(Default, Clone, Debug)]
+pub struct SseEvent {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl SseEvent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+    /// Panics if `id` contains a line break or a NUL.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        let id = id.into();
+        assert!(
+            !id.contains(['\r', '\n', '\0']),
+            "sse event id must not contain line breaks or NUL"
+        );
+        self.id = Some(id);
+        self
+    }
+    pub fn event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+    /// Panics if `event` contains a line break.
+    pub fn with_event(mut self, event: impl Into<String>) -> Self {
+        let event = event.into();
+        assert!(
+            !event.contains(['\r', '\n']),
+            "sse event name must not contain line breaks"
+        );
+        self.event = Some(event);
+        self
+    }
+    pub fn data(&self) -> Option<&str> {
+        self.data.as_deref()
+    }
+    /// Multi-line data is sent as one `data:` field per line.
+    pub fn with_data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+    pub fn with_json_data<T: Serialize>(mut self, data: &T) -> crate::Result<Self> {
+        self.data = Some(serde_json::to_string(data)?);
+        Ok(self)
+    }
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+    pub fn with_retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+}
+
+/// Splits on `\r\n`, `\r` and `\n`, which all end a line in an event stream, so a lone `\r`
+/// can not start a new field.
+fn split_lines(value: &str) -> Vec<&str> {
+    let mut lines = value
+        .split("\r\n")
+        .flat_map(|line| line.split(['\r', '\n']))
+        .collect::<Vec<_>>();
+    if lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines
+}
+
+impl Display for SseEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(comment) = &self.comment {
+            if comment.is_empty() {
+                f.write_str(":\n")?;
+            }
+            for line in split_lines(comment) {
+                writeln!(f, ":{}", line)?;
+            }
+        }
+        if let Some(event) = &self.event {
+            writeln!(f, "event:{}", event)?;
+        }
+        if let Some(data) = &self.data {
+            if data.is_empty() {
+                f.write_str("data:\n")?;
+            }
+            for line in split_lines(data) {
+                writeln!(f, "data:{}", line)?;
+            }
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id:{}", id)?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry:{}", retry.as_millis())?;
+        }
+        f.write_char('\n')
+    }
+}
+
+/// Sends a comment whenever the event stream stays idle for `interval`, so proxies keep the connection open.
+#[derive(Clone, Debug)]
+pub struct SseKeepAlive {
+    comment: Cow<'static, str>,
+    interval: Duration,
+}
+
+impl Default for SseKeepAlive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SseKeepAlive {
+    pub fn new() -> Self {
+        SseKeepAlive {
+            comment: Cow::Borrowed(""),
+            interval: DEFAULT_KEEP_ALIVE_INTERVAL,
+        }
+    }
+    pub fn comment(&self) -> &str {
+        &self.comment
+    }
+    pub fn with_comment(mut self, comment: impl Into<Cow<'static, str>>) -> Self {
+        self.comment = comment.into();
+        self
+    }
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn stream<S, E>(self, event_stream: S) -> impl Stream<Item = Result<SseEvent, E>>
+    where
+        S: Stream<Item = Result<SseEvent, E>>,
+    {
+        KeepAliveStream {
+            event_stream: Box::pin(event_stream),
+            comment: self.comment,
+            interval: self.interval,
+            alive_timer: Box::pin(time::sleep(self.interval)),
+        }
+    }
+}
+
+struct KeepAliveStream<S> {
+    event_stream: Pin<Box<S>>,
+    comment: Cow<'static, str>,
+    interval: Duration,
+    alive_timer: Pin<Box<Sleep>>,
+}
+
+impl<S, E> Stream for KeepAliveStream<S>
+where
+    S: Stream<Item = Result<SseEvent, E>>,
+{
+    type Item = Result<SseEvent, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match this.event_stream.as_mut().poll_next(cx) {
+            Poll::Pending => match this.alive_timer.as_mut().poll(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(()) => {
+                    this.alive_timer
+                        .as_mut()
+                        .reset(Instant::now() + this.interval);
+                    let event = SseEvent::new().with_comment(this.comment.clone());
+                    Poll::Ready(Some(Ok(event)))
+                }
+            },
+            Poll::Ready(Some(item)) => {
+                this.alive_timer
+                    .as_mut()
+                    .reset(Instant::now() + this.interval);
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+        }
+    }
+}
+
+/// Streams `SseEvent`s as `text/event-stream`.
+pub struct Sse<S>(pub S);
+
+impl<S> Sse<S> {
+    pub fn new(event_stream: S) -> Self {
+        Sse(event_stream)
+    }
+}
+
+impl<S, E> Piece for Sse<S>
+where
+    S: Stream<Item = Result<SseEvent, E>> + Send + Sync + 'static,
+    E: Into<BoxedError> + 'static,
+{
+    fn render(self, res: &mut Response) {
+        let headers = res.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        let body = self.0.map_ok(|event| event.to_string()).map_err(Into::into);
+        if let Err(e) = res.streaming::<_, _, BoxedError>(body) {
+            tracing::error!(error = ?e, "failed to stream sse events");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::stream::{self, StreamExt};
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    #[test]
+    fn test_sse_event_format() {
+        let event = SseEvent::new()
+            .with_comment("hello")
+            .with_event("update")
+            .with_data("line1\nline2")
+            .with_id("42")
+            .with_retry(Duration::from_secs(3));
+        assert_eq!(
+            event.to_string(),
+            ":hello\nevent:update\ndata:line1\ndata:line2\nid:42\nretry:3000\n\n"
+        );
+        assert_eq!(SseEvent::new().with_data("").to_string(), "data:\n\n");
+        assert_eq!(
+            SseEvent::new()
+                .with_comment("a\rid:1")
+                .with_data("b\revent:x\r\nc\r")
+                .to_string(),
+            ":a\n:id:1\ndata:b\ndata:event:x\ndata:c\n\n"
+        );
+        assert_eq!(SseEvent::new().with_comment("").to_string(), ":\n\n");
+        assert_eq!(
+            SseEvent::new()
+                .with_json_data(&serde_json::json!({"a": 1}))
+                .unwrap()
+                .to_string(),
+            "data:{\"a\":1}\n\n"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_sse_event_invalid_id() {
+        let _ = SseEvent::new().with_id("1\n2");
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream() {
+        #[handler(internal)]
+        async fn events(req: &mut Request, res: &mut Response) {
+            let start = last_event_id(req)
+                .and_then(|id| id.parse::<u32>().ok())
+                .map(|id| id + 1)
+                .unwrap_or(0);
+            let ticks = stream::iter(start..3).map(|i| {
+                Ok::<_, Infallible>(SseEvent::new().with_id(i.to_string()).with_data("tick"))
+            });
+            res.render(Sse::new(ticks));
+        }
+        let router = Router::with_path("events").get(events);
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/events")
+            .send(router)
+            .await;
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        assert_eq!(res.headers().get(CACHE_CONTROL).unwrap(), "no-cache");
+        assert_eq!(
+            res.take_string().await.unwrap(),
+            "data:tick\nid:0\n\ndata:tick\nid:1\n\ndata:tick\nid:2\n\n"
+        );
+
+        let router = Router::with_path("events").get(events);
+        let mut res = TestClient::get("http://127.0.0.1:7878/events")
+            .add_header("last-event-id", "1", true)
+            .send(router)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "data:tick\nid:2\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_sse_keep_alive() {
+        let events = stream::once(async {
+            time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, Infallible>(SseEvent::new().with_data("done"))
+        });
+        let events = SseKeepAlive::new()
+            .with_comment("ping")
+            .with_interval(Duration::from_millis(20))
+            .stream(events)
+            .map(|event| event.unwrap().to_string())
+            .collect::<Vec<_>>()
+            .await;
+        assert!(events.len() > 1);
+        assert!(events[..events.len() - 1].iter().all(|e| e == ":ping\n\n"));
+        assert_eq!(events.last().unwrap(), "data:done\n\n");
+
+        let mut res = Response::new();
+        res.render(Sse::new(
+            SseKeepAlive::new().stream(stream::empty::<Result<SseEvent, Infallible>>()),
+        ));
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+    }
+}
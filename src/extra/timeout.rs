@@ -0,0 +1,238 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+
+use crate::http::{Request, ResBody, Response, StatusError};
+use crate::{async_trait, Depot, FlowCtrl, Handler};
+
+/// Key of the deadline shared between `Timeout` and the handlers it wraps.
+pub const TIMEOUT_KEY: &str = "::salvo::timeout";
+
+struct Deadline {
+    started_at: Instant,
+    expires_at: Mutex<Instant>,
+    error: Mutex<StatusError>,
+    changed: Notify,
+}
+
+impl Deadline {
+    fn set(&self, value: Duration) {
+        *self.expires_at.lock().unwrap() = self.started_at + value;
+        self.changed.notify_one();
+    }
+    fn get(&self) -> Instant {
+        *self.expires_at.lock().unwrap()
+    }
+}
+
+/// Overrides the deadline of the enclosing `Timeout` from inside a route.
+pub trait TimeoutDepotExt {
+    /// Replaces the timeout of the current request, counted from when `Timeout` started.
+    ///
+    /// Does nothing if the request is not wrapped in a `Timeout`.
+    fn set_timeout(&mut self, value: Duration);
+    /// Time left before the request times out.
+    fn timeout_remaining(&self) -> Option<Duration>;
+}
+
+impl TimeoutDepotExt for Depot {
+    fn set_timeout(&mut self, value: Duration) {
+        if let Some(deadline) = self.get::<Arc<Deadline>>(TIMEOUT_KEY) {
+            deadline.set(value);
+        }
+    }
+    fn timeout_remaining(&self) -> Option<Duration> {
+        self.get::<Arc<Deadline>>(TIMEOUT_KEY)
+            .map(|deadline| deadline.get().saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Stops the rest of the flow once `value` has elapsed and renders `StatusError::service_unavailable()`,
+/// or the error given to `with_error`.
+///
+/// The pending handlers are dropped and the flow is ceased, so outer hoops can check
+/// `FlowCtrl::is_ceased`. A `Timeout` nested inside another one does not start a timer of
+/// its own but overrides the deadline and the error of the outer one.
+pub struct Timeout {
+    value: Duration,
+    error: StatusError,
+}
+
+impl Timeout {
+    pub fn new(value: Duration) -> Self {
+        Timeout {
+            value,
+            error: StatusError::service_unavailable(),
+        }
+    }
+    pub fn value(&self) -> Duration {
+        self.value
+    }
+    pub fn with_value(mut self, value: Duration) -> Self {
+        self.value = value;
+        self
+    }
+    pub fn error(&self) -> &StatusError {
+        &self.error
+    }
+    /// Use `StatusError::gateway_timeout()` for handlers waiting on an upstream server.
+    pub fn with_error(mut self, error: StatusError) -> Self {
+        self.error = error;
+        self
+    }
+}
+
+#[async_trait]
+impl Handler for Timeout {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        if let Some(deadline) = depot.get::<Arc<Deadline>>(TIMEOUT_KEY) {
+            *deadline.error.lock().unwrap() = self.error.clone();
+            deadline.set(self.value);
+            ctrl.call_next(req, depot, res).await;
+            return;
+        }
+        let started_at = Instant::now();
+        let deadline = Arc::new(Deadline {
+            started_at,
+            expires_at: Mutex::new(started_at + self.value),
+            error: Mutex::new(self.error.clone()),
+            changed: Notify::new(),
+        });
+        depot.insert(TIMEOUT_KEY, deadline.clone());
+
+        let completed = {
+            let next = ctrl.call_next(req, depot, res);
+            tokio::pin!(next);
+            loop {
+                tokio::select! {
+                    _ = &mut next => break true,
+                    _ = time::sleep_until(deadline.get()) => {
+                        if deadline.get() <= Instant::now() {
+                            break false;
+                        }
+                    }
+                    _ = deadline.changed.notified() => {}
+                }
+            }
+        };
+        if !completed {
+            tracing::debug!(elapsed = ?started_at.elapsed(), "request timed out");
+            res.set_body(ResBody::None);
+            let error = deadline.error.lock().unwrap().clone();
+            res.set_status_error(error);
+            ctrl.cease();
+        }
+        depot.remove::<Arc<Deadline>>(TIMEOUT_KEY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    use super::*;
+
+    #[handler(internal)]
+    async fn slow() -> &'static str {
+        time::sleep(Duration::from_millis(200)).await;
+        "done"
+    }
+    #[handler(internal)]
+    async fn fast() -> &'static str {
+        "fast"
+    }
+    #[handler(internal)]
+    async fn extended(depot: &mut Depot) -> &'static str {
+        depot.set_timeout(Duration::from_secs(5));
+        time::sleep(Duration::from_millis(200)).await;
+        "extended"
+    }
+    #[handler(internal)]
+    async fn check_ceased(
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        ctrl.call_next(req, depot, res).await;
+        if ctrl.is_ceased() {
+            res.headers_mut()
+                .insert("x-ceased", "true".parse().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let router = Router::with_hoop(check_ceased)
+            .hoop(Timeout::new(Duration::from_millis(50)))
+            .push(Router::with_path("slow").get(slow))
+            .push(Router::with_path("fast").get(fast))
+            .push(Router::with_path("extended").get(extended))
+            .push(
+                Router::with_path("nested")
+                    .hoop(Timeout::new(Duration::from_secs(5)))
+                    .get(slow),
+            )
+            .push(
+                Router::with_path("gateway")
+                    .hoop(
+                        Timeout::new(Duration::from_millis(10))
+                            .with_error(StatusError::gateway_timeout()),
+                    )
+                    .get(slow),
+            );
+        let service = Service::new(router);
+
+        let res = TestClient::get("http://127.0.0.1:7878/slow")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(res.headers()["x-ceased"], "true");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/fast")
+            .send(&service)
+            .await;
+        assert!(res.headers().get("x-ceased").is_none());
+        assert_eq!(res.take_string().await.unwrap(), "fast");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/extended")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "extended");
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/nested")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "done");
+
+        let res = TestClient::get("http://127.0.0.1:7878/gateway")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::GATEWAY_TIMEOUT));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_shortened() {
+        #[handler(internal)]
+        async fn shortened(depot: &mut Depot) -> &'static str {
+            depot.set_timeout(Duration::from_millis(20));
+            assert!(depot.timeout_remaining().unwrap() <= Duration::from_millis(20));
+            time::sleep(Duration::from_secs(5)).await;
+            "done"
+        }
+        let router = Router::with_hoop(Timeout::new(Duration::from_secs(10))).get(shortened);
+        let started_at = Instant::now();
+        let res = TestClient::get("http://127.0.0.1:7878/").send(router).await;
+        assert_eq!(res.status_code(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+    }
+}
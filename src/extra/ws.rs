@@ -0,0 +1,428 @@
+use std::borrow::Cow;
+use std::fmt::{self, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::{ready, Sink, SinkExt, Stream, StreamExt};
+use hyper::upgrade::{OnUpgrade, Upgraded};
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{self, CloseFrame, Role, WebSocketConfig};
+use tokio_tungstenite::WebSocketStream;
+
+use crate::http::header::{
+    HeaderValue, CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL,
+    SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use crate::http::{Method, Request, Response, StatusCode, StatusError};
+use crate::Error;
+
+/// Validates a WebSocket handshake and hands the upgraded connection to a callback.
+///
+/// ```ignore
+/// #[handler]
+/// async fn connect(req: &mut Request, res: &mut Response) -> Result<(), StatusError> {
+///     WebSocketUpgrade::new()
+///         .upgrade(req, res, |mut ws| async move {
+///             while let Some(Ok(msg)) = ws.recv().await {
+///                 if ws.send(msg).await.is_err() {
+///                     return;
+///                 }
+///             }
+///         })
+///         .await
+/// }
+/// ```
+#[derive(Default, Clone, Debug)]
+pub struct WebSocketUpgrade {
+    config: Option<WebSocketConfig>,
+    protocols: Vec<String>,
+}
+
+impl WebSocketUpgrade {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_config(mut self, config: WebSocketConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+    pub fn with_max_send_queue(mut self, max: usize) -> Self {
+        self.config
+            .get_or_insert_with(WebSocketConfig::default)
+            .max_send_queue = Some(max);
+        self
+    }
+    pub fn with_max_message_size(mut self, max: usize) -> Self {
+        self.config
+            .get_or_insert_with(WebSocketConfig::default)
+            .max_message_size = Some(max);
+        self
+    }
+    pub fn with_max_frame_size(mut self, max: usize) -> Self {
+        self.config
+            .get_or_insert_with(WebSocketConfig::default)
+            .max_frame_size = Some(max);
+        self
+    }
+    /// Subprotocols supported by the server, the first one also offered by the client is selected.
+    pub fn with_protocols<I, P>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Answers the handshake with `101 Switching Protocols` and runs `callback` on the
+    /// upgraded connection in a new task.
+    pub async fn upgrade<F, Fut>(
+        &self,
+        req: &mut Request,
+        res: &mut Response,
+        callback: F,
+    ) -> Result<(), StatusError>
+    where
+        F: FnOnce(WebSocket) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if req.method() != Method::GET {
+            return Err(
+                StatusError::method_not_allowed().with_summary("websocket upgrade requires GET")
+            );
+        }
+        if !header_contains(req, CONNECTION, "upgrade") {
+            return Err(
+                StatusError::bad_request().with_summary("missing connection upgrade header")
+            );
+        }
+        if !header_contains(req, UPGRADE, "websocket") {
+            return Err(StatusError::bad_request().with_summary("missing upgrade websocket header"));
+        }
+        if req
+            .headers()
+            .get(SEC_WEBSOCKET_VERSION)
+            .map(|v| v.as_bytes())
+            != Some(b"13")
+        {
+            return Err(StatusError::bad_request().with_summary("unsupported websocket version"));
+        }
+        let key = req
+            .headers()
+            .get(SEC_WEBSOCKET_KEY)
+            .ok_or_else(|| StatusError::bad_request().with_summary("missing websocket key"))?;
+        let accept = derive_accept_key(key.as_bytes());
+        let protocol = req
+            .headers()
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .find(|p| self.protocols.iter().any(|s| s == p))
+            .map(ToOwned::to_owned);
+        let on_upgrade = req.extensions_mut().remove::<OnUpgrade>().ok_or_else(|| {
+            StatusError::bad_request().with_summary("connection is not upgradable")
+        })?;
+
+        res.set_status_code(StatusCode::SWITCHING_PROTOCOLS);
+        let headers = res.headers_mut();
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(
+            SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_str(&accept).expect("accept key is always a valid header value"),
+        );
+        if let Some(protocol) = &protocol {
+            if let Ok(value) = HeaderValue::from_str(protocol) {
+                headers.insert(SEC_WEBSOCKET_PROTOCOL, value);
+            }
+        }
+
+        let config = self.config;
+        tokio::spawn(async move {
+            match on_upgrade.await {
+                Ok(upgraded) => {
+                    let inner =
+                        WebSocketStream::from_raw_socket(upgraded, Role::Server, config).await;
+                    callback(WebSocket { inner, protocol }).await;
+                }
+                Err(e) => tracing::error!(error = ?e, "websocket upgrade failed"),
+            }
+        });
+        Ok(())
+    }
+}
+
+fn header_contains(req: &Request, name: crate::http::header::HeaderName, token: &str) -> bool {
+    req.headers()
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.trim().eq_ignore_ascii_case(token))
+}
+
+/// An upgraded WebSocket connection, a `Stream` of received messages and a `Sink` of messages to send.
+pub struct WebSocket {
+    inner: WebSocketStream<Upgraded>,
+    protocol: Option<String>,
+}
+
+impl WebSocket {
+    /// Subprotocol negotiated during the handshake.
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+    /// Receives the next message, `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<Message, Error>> {
+        self.next().await
+    }
+    pub async fn send(&mut self, msg: Message) -> Result<(), Error> {
+        SinkExt::send(self, msg).await
+    }
+    /// Sends a close frame and flushes the connection.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.inner.close(None).await.map_err(Error::other)
+    }
+}
+
+impl fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("protocol", &self.protocol)
+            .finish()
+    }
+}
+
+impl Stream for WebSocket {
+    type Item = Result<Message, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+            Some(Ok(inner)) => Poll::Ready(Some(Ok(Message { inner }))),
+            Some(Err(e)) => Poll::Ready(Some(Err(Error::other(e)))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl Sink<Message> for WebSocket {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_ready(cx)
+            .map_err(Error::other)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), Self::Error> {
+        Pin::new(&mut self.inner)
+            .start_send(item.inner)
+            .map_err(Error::other)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(Error::other)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(Error::other)
+    }
+}
+
+/// A WebSocket message: text, binary, ping, pong or close.
+#[derive(Eq, PartialEq, Clone)]
+pub struct Message {
+    inner: protocol::Message,
+}
+
+impl Message {
+    pub fn text(text: impl Into<String>) -> Message {
+        Message {
+            inner: protocol::Message::text(text),
+        }
+    }
+    pub fn binary(data: impl Into<Vec<u8>>) -> Message {
+        Message {
+            inner: protocol::Message::binary(data),
+        }
+    }
+    pub fn ping(data: impl Into<Vec<u8>>) -> Message {
+        Message {
+            inner: protocol::Message::Ping(data.into()),
+        }
+    }
+    pub fn pong(data: impl Into<Vec<u8>>) -> Message {
+        Message {
+            inner: protocol::Message::Pong(data.into()),
+        }
+    }
+    pub fn close() -> Message {
+        Message {
+            inner: protocol::Message::Close(None),
+        }
+    }
+    pub fn close_with(code: impl Into<u16>, reason: impl Into<Cow<'static, str>>) -> Message {
+        Message {
+            inner: protocol::Message::Close(Some(CloseFrame {
+                code: CloseCode::from(code.into()),
+                reason: reason.into(),
+            })),
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        self.inner.is_text()
+    }
+    pub fn is_binary(&self) -> bool {
+        self.inner.is_binary()
+    }
+    pub fn is_ping(&self) -> bool {
+        self.inner.is_ping()
+    }
+    pub fn is_pong(&self) -> bool {
+        self.inner.is_pong()
+    }
+    pub fn is_close(&self) -> bool {
+        self.inner.is_close()
+    }
+    /// Close code and reason, if this is a close message carrying a frame.
+    pub fn close_frame(&self) -> Option<(u16, &str)> {
+        match &self.inner {
+            protocol::Message::Close(Some(frame)) => {
+                Some((frame.code.into(), frame.reason.as_ref()))
+            }
+            _ => None,
+        }
+    }
+    pub fn to_str(&self) -> Result<&str, Error> {
+        match &self.inner {
+            protocol::Message::Text(text) => Ok(text),
+            _ => Err(Error::other("not a text message")),
+        }
+    }
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.inner {
+            protocol::Message::Text(text) => text.as_bytes(),
+            protocol::Message::Binary(data)
+            | protocol::Message::Ping(data)
+            | protocol::Message::Pong(data) => data,
+            protocol::Message::Close(Some(frame)) => frame.reason.as_bytes(),
+            protocol::Message::Close(None) => &[],
+            protocol::Message::Frame(frame) => frame.payload(),
+        }
+    }
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.inner.into_data()
+    }
+}
+
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl From<Message> for Vec<u8> {
+    fn from(msg: Message) -> Self {
+        msg.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message as RawMessage;
+
+    use super::*;
+    use crate::prelude::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    #[handler(internal)]
+    async fn echo(req: &mut Request, res: &mut Response) -> Result<(), StatusError> {
+        WebSocketUpgrade::new()
+            .with_protocols(["echo"])
+            .upgrade(req, res, |mut ws| async move {
+                while let Some(Ok(msg)) = ws.recv().await {
+                    if msg.is_close() {
+                        break;
+                    }
+                    if ws.send(msg).await.is_err() {
+                        return;
+                    }
+                }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_websocket() {
+        let listener = TcpListener::bind("127.0.0.1:0");
+        let addr = listener.local_addr();
+        tokio::spawn(async move {
+            Server::new(listener)
+                .serve(Router::with_path("ws").get(echo))
+                .await;
+        });
+
+        let (mut ws, resp) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+        ws.send(RawMessage::text("hello")).await.unwrap();
+        assert_eq!(ws.next().await.unwrap().unwrap(), RawMessage::text("hello"));
+        ws.send(RawMessage::binary(vec![1, 2, 3])).await.unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            RawMessage::binary(vec![1, 2, 3])
+        );
+        ws.send(RawMessage::Ping(b"ping".to_vec())).await.unwrap();
+        assert_eq!(
+            ws.next().await.unwrap().unwrap(),
+            RawMessage::Pong(b"ping".to_vec())
+        );
+        ws.close(None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_bad_handshake() {
+        let service = Service::new(Router::with_path("ws").get(echo));
+
+        let res = TestClient::get("http://127.0.0.1:7878/ws")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::BAD_REQUEST));
+
+        let mut res = TestClient::get("http://127.0.0.1:7878/ws")
+            .add_header(CONNECTION, "keep-alive, Upgrade", true)
+            .add_header(UPGRADE, "websocket", true)
+            .add_header(SEC_WEBSOCKET_VERSION, "8", true)
+            .add_header(SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::BAD_REQUEST));
+        assert!(res
+            .take_string()
+            .await
+            .unwrap()
+            .contains("unsupported websocket version"));
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(Message::text("hi").to_str().unwrap(), "hi");
+        assert!(Message::binary(vec![1]).to_str().is_err());
+        assert_eq!(Message::ping("p").as_bytes(), b"p");
+        let close = Message::close_with(1000u16, "bye");
+        assert!(close.is_close());
+        assert_eq!(close.close_frame(), Some((1000, "bye")));
+    }
+}
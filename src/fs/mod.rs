@@ -0,0 +1,3 @@
+mod named_file;
+
+pub use named_file::NamedFile;
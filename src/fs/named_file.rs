@@ -0,0 +1,419 @@
+use std::fs::Metadata;
+use std::io::{Error as IoError, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use futures_util::{future, stream, StreamExt, TryStreamExt};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::http::header::{
+    HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use crate::http::{HttpRange, Method, Mime, Request, ResBody, Response, StatusCode, StatusError};
+use crate::{async_trait, Depot, Writer};
+
+const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A file on disk which can be sent as response, it answers conditional and `Range` requests.
+pub struct NamedFile {
+    path: PathBuf,
+    file: File,
+    metadata: Metadata,
+    content_type: Mime,
+    buffer_size: usize,
+}
+
+impl NamedFile {
+    pub async fn open(path: impl Into<PathBuf>) -> crate::Result<NamedFile> {
+        let path = path.into();
+        let file = File::open(&path).await?;
+        let metadata = file.metadata().await?;
+        if metadata.is_dir() {
+            return Err(IoError::new(ErrorKind::InvalidInput, "path is a directory").into());
+        }
+        let content_type = mime_guess::from_path(&path).first_or_octet_stream();
+        Ok(NamedFile {
+            path,
+            file,
+            metadata,
+            content_type,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        })
+    }
+    pub fn with_content_type(mut self, content_type: Mime) -> Self {
+        self.content_type = content_type;
+        self
+    }
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+    pub fn content_type(&self) -> &Mime {
+        &self.content_type
+    }
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.metadata.modified().ok()
+    }
+    pub fn etag(&self) -> Option<String> {
+        let modified = self.modified()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(format!(
+            "\"{:x}-{:x}.{:x}\"",
+            self.metadata.len(),
+            modified.as_secs(),
+            modified.subsec_nanos()
+        ))
+    }
+
+    pub async fn send(self, req: &Request, res: &mut Response) {
+        let size = self.metadata.len();
+        let etag = self.etag();
+        // Http dates only have a precision of one second.
+        let modified = self.modified().and_then(|modified| {
+            let secs = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+            Some(UNIX_EPOCH + Duration::from_secs(secs))
+        });
+        let is_get_or_head = *req.method() == Method::GET || *req.method() == Method::HEAD;
+
+        if let Some(etag) = &etag {
+            res.headers_mut()
+                .insert(ETAG, HeaderValue::from_str(etag).unwrap());
+        }
+        if let Some(modified) = modified {
+            res.headers_mut().insert(
+                LAST_MODIFIED,
+                HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap(),
+            );
+        }
+
+        if let Some(if_none_match) = header_str(req, IF_NONE_MATCH) {
+            if etag_matches(if_none_match, etag.as_deref(), false) {
+                if is_get_or_head {
+                    res.set_status_code(StatusCode::NOT_MODIFIED);
+                } else {
+                    res.set_status_error(StatusError::precondition_failed());
+                }
+                return;
+            }
+        } else if let (Some(since), Some(modified), true) = (
+            header_str(req, IF_MODIFIED_SINCE).and_then(|v| httpdate::parse_http_date(v).ok()),
+            modified,
+            is_get_or_head,
+        ) {
+            if modified <= since {
+                res.set_status_code(StatusCode::NOT_MODIFIED);
+                return;
+            }
+        }
+
+        let content_type = if self.content_type.type_() == mime::TEXT
+            && self.content_type.get_param(mime::CHARSET).is_none()
+        {
+            format!("{}; charset=utf-8", self.content_type)
+        } else {
+            self.content_type.to_string()
+        };
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&content_type).unwrap(),
+        );
+        res.headers_mut()
+            .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+        let mut ranges = vec![];
+        let range = header_str(req, RANGE).filter(|_| *req.method() == Method::GET);
+        if let Some(range) = range {
+            let fresh = match header_str(req, IF_RANGE) {
+                None => true,
+                Some(if_range) if if_range.starts_with('"') || if_range.starts_with("W/") => {
+                    etag_matches(if_range, etag.as_deref(), true)
+                }
+                Some(if_range) => httpdate::parse_http_date(if_range).ok() == modified,
+            };
+            if fresh && range.starts_with("bytes=") {
+                // Zero-length ranges, as `bytes=-0` or any suffix range of an empty file, select
+                // nothing and can not be written as a `Content-Range`.
+                match HttpRange::parse(range, size) {
+                    Ok(parsed) if parsed.iter().all(|r| r.length > 0) => {
+                        if parsed.iter().map(|r| r.length).sum::<u64>() <= size {
+                            ranges = parsed;
+                        }
+                    }
+                    _ => {
+                        res.headers_mut().insert(
+                            CONTENT_RANGE,
+                            HeaderValue::from_str(&format!("bytes */{}", size)).unwrap(),
+                        );
+                        res.set_status_error(StatusError::range_not_satisfiable());
+                        return;
+                    }
+                }
+            }
+        }
+
+        match ranges.len() {
+            0 => {
+                res.set_status_code(StatusCode::OK);
+                res.headers_mut().insert(CONTENT_LENGTH, size.into());
+                if *req.method() != Method::HEAD {
+                    let body = ReaderStream::with_capacity(self.file, self.buffer_size);
+                    res.set_body(ResBody::Stream(Box::pin(body.map_err(Into::into))));
+                }
+            }
+            1 => {
+                let range = ranges[0];
+                let mut file = self.file;
+                if let Err(e) = file.seek(SeekFrom::Start(range.start)).await {
+                    tracing::error!(error = ?e, path = ?self.path, "seek file failed");
+                    res.set_status_error(StatusError::internal_server_error());
+                    return;
+                }
+                res.set_status_code(StatusCode::PARTIAL_CONTENT);
+                res.headers_mut()
+                    .insert(CONTENT_RANGE, content_range(&range, size));
+                res.headers_mut().insert(CONTENT_LENGTH, range.length.into());
+                let body =
+                    ReaderStream::with_capacity(file.take(range.length), self.buffer_size);
+                res.set_body(ResBody::Stream(Box::pin(body.map_err(Into::into))));
+            }
+            _ => {
+                let boundary: String = std::iter::repeat_with(fastrand::alphanumeric)
+                    .take(32)
+                    .collect();
+                res.set_status_code(StatusCode::PARTIAL_CONTENT);
+                res.headers_mut().insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary))
+                        .unwrap(),
+                );
+                let closing = Bytes::from(format!("--{}--\r\n", boundary));
+                let path = self.path;
+                let buffer_size = self.buffer_size;
+                let parts = ranges.into_iter().map(move |range| {
+                    let head = format!(
+                        "--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        boundary,
+                        content_type,
+                        range.start,
+                        range.start + range.length - 1,
+                        size
+                    );
+                    (Bytes::from(head), range, path.clone())
+                });
+                let body = stream::iter(parts)
+                    .then(move |(head, range, path)| async move {
+                        let mut file = File::open(&path).await?;
+                        file.seek(SeekFrom::Start(range.start)).await?;
+                        let data = ReaderStream::with_capacity(file.take(range.length), buffer_size);
+                        Ok::<_, IoError>(
+                            stream::once(future::ok(head))
+                                .chain(data)
+                                .chain(stream::once(future::ok(Bytes::from_static(b"\r\n")))),
+                        )
+                    })
+                    .try_flatten()
+                    .chain(stream::once(future::ok(closing)));
+                res.set_body(ResBody::Stream(Box::pin(body.map_err(Into::into))));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Writer for NamedFile {
+    async fn write(mut self, req: &mut Request, _depot: &mut Depot, res: &mut Response) {
+        self.send(req, res).await;
+    }
+}
+
+fn header_str(req: &Request, name: impl crate::http::header::AsHeaderName) -> Option<&str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn content_range(range: &HttpRange, size: u64) -> HeaderValue {
+    HeaderValue::from_str(&format!(
+        "bytes {}-{}/{}",
+        range.start,
+        range.start + range.length - 1,
+        size
+    ))
+    .unwrap()
+}
+
+/// Compares an `If-None-Match`/`If-Range` value with the current etag, `strong` disallows weak tags.
+fn etag_matches(header: &str, etag: Option<&str>, strong: bool) -> bool {
+    let etag = match etag {
+        Some(etag) => etag,
+        None => return false,
+    };
+    header.split(',').map(|tag| tag.trim()).any(|tag| {
+        if tag == "*" {
+            return !strong;
+        }
+        if strong {
+            tag == etag
+        } else {
+            tag.trim_start_matches("W/") == etag.trim_start_matches("W/")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::test::{ResponseExt, TestClient};
+
+    async fn serve(req: Request, path: &Path) -> Response {
+        let mut res = Response::new();
+        NamedFile::open(path).await.unwrap().send(&req, &mut res).await;
+        res
+    }
+
+    fn boundary_of(res: &Response) -> String {
+        res.headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split_once("boundary="))
+            .map(|(_, boundary)| boundary.to_owned())
+            .unwrap_or_default()
+    }
+
+    fn temp_file() -> tempfile::NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(".txt").tempfile().unwrap();
+        file.write_all(b"0123456789").unwrap();
+        file
+    }
+
+    #[tokio::test]
+    async fn test_send_file() {
+        let file = temp_file();
+        let req = TestClient::get("http://127.0.0.1:7878/").build();
+        let mut res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+        assert_eq!(
+            res.headers().get(CONTENT_TYPE).unwrap(),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10");
+        assert_eq!(res.headers().get(ACCEPT_RANGES).unwrap(), "bytes");
+        assert!(res.headers().contains_key(ETAG));
+        assert_eq!(res.take_string().await.unwrap(), "0123456789");
+
+        let req = TestClient::head("http://127.0.0.1:7878/").build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.headers().get(CONTENT_LENGTH).unwrap(), "10");
+        assert!(res.body().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_range() {
+        let file = temp_file();
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(RANGE, "bytes=2-4", true)
+            .build();
+        let mut res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::PARTIAL_CONTENT));
+        assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes 2-4/10");
+        assert_eq!(res.take_string().await.unwrap(), "234");
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(RANGE, "bytes=0-1,-2", true)
+            .build();
+        let mut res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::PARTIAL_CONTENT));
+        let boundary = boundary_of(&res);
+        assert_eq!(
+            res.take_string().await.unwrap(),
+            format!(
+                "--{0}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+                --{0}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+                --{0}--\r\n",
+                boundary
+            )
+        );
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(RANGE, "bytes=20-30", true)
+            .build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::RANGE_NOT_SATISFIABLE));
+        assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes */10");
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(RANGE, "bytes=-0", true)
+            .build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::RANGE_NOT_SATISFIABLE));
+        assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes */10");
+    }
+
+    #[tokio::test]
+    async fn test_send_range_of_empty_file() {
+        let file = tempfile::Builder::new().suffix(".txt").tempfile().unwrap();
+        for range in ["bytes=-5", "bytes=0-", "bytes=0-1,-2"] {
+            let req = TestClient::get("http://127.0.0.1:7878/")
+                .add_header(RANGE, range, true)
+                .build();
+            let res = serve(req, file.path()).await;
+            assert_eq!(res.status_code(), Some(StatusCode::RANGE_NOT_SATISFIABLE));
+            assert_eq!(res.headers().get(CONTENT_RANGE).unwrap(), "bytes */0");
+        }
+
+        let req = TestClient::get("http://127.0.0.1:7878/").build();
+        let mut res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+        assert_eq!(res.take_string().await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_conditional() {
+        let file = temp_file();
+        let named = NamedFile::open(file.path()).await.unwrap();
+        let etag = named.etag().unwrap();
+        let modified = httpdate::fmt_http_date(named.modified().unwrap());
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(IF_NONE_MATCH, format!("\"other\", {}", etag), true)
+            .build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::NOT_MODIFIED));
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(IF_MODIFIED_SINCE, modified.clone(), true)
+            .build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::NOT_MODIFIED));
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT", true)
+            .build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(RANGE, "bytes=0-1", true)
+            .add_header(IF_RANGE, etag, true)
+            .build();
+        let res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::PARTIAL_CONTENT));
+
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(RANGE, "bytes=0-1", true)
+            .add_header(IF_RANGE, "\"stale\"", true)
+            .build();
+        let mut res = serve(req, file.path()).await;
+        assert_eq!(res.status_code(), Some(StatusCode::OK));
+        assert_eq!(res.take_string().await.unwrap(), "0123456789");
+    }
+}
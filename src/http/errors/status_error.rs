@@ -2,6 +2,7 @@ use std::{error::Error as StdError, fmt::Display};
 
 use async_trait::async_trait;
 use hyper::StatusCode;
+use serde_json::{Map, Value};
 
 use crate::{writer::Writer, http::{response::Response, request::Request}, depot::Depot};
 
@@ -16,6 +17,7 @@ macro_rules! default_errors {
                     name: $name.into(),
                     summary: Some($summary.into()),
                     detail: None,
+                    extensions: Map::new(),
                 }
             }
         )+
@@ -28,12 +30,15 @@ macro_rules! default_errors {
 //         )+
 //     };
 // }
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct StatusError {
     pub code: StatusCode,
     pub name: String,
     pub summary: Option<String>,
     pub detail: Option<String>,
+    /// Extra members of RFC 7807 problem details, e.g. validation errors. The `type` and
+    /// `instance` members are kept here too.
+    pub extensions: Map<String, Value>,
 }
 
 impl StatusError {
@@ -45,6 +50,19 @@ impl StatusError {
         self.detail = Some(detail.into());
         self
     }
+    /// URI of the problem type, `about:blank` by default.
+    pub fn with_problem_type(self, problem_type: impl Into<String>) -> Self {
+        self.with_extension("type", problem_type.into())
+    }
+    /// URI of this occurrence of the problem, the request path by default.
+    pub fn with_instance(self, instance: impl Into<String>) -> Self {
+        self.with_extension("instance", instance.into())
+    }
+    /// Adds a problem details member, `title`, `status` and `detail` come from the other fields.
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extensions.insert(name.into(), value.into());
+        self
+    }
     default_errors! {
     bad_request,                        StatusCode::BAD_REQUEST,            "Bad Request", "The request could not be understood by the server due to malformed syntax.";
     unauthorized,                       StatusCode::UNAUTHORIZED,           "Unauthorized", "The request requires user authentication.";
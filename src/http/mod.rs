@@ -1,5 +1,6 @@
 pub mod errors;
 pub mod form;
+pub mod negotiation;
 mod range;
 pub mod request;
 pub mod response;
@@ -12,15 +13,16 @@ pub use range::HttpRange;
 pub use request::{ReqBody, Request};
 pub use response::{ResBody, Response};
 
+/// The accepted media type with the highest q-value, without its params. `default_type`, or
+/// `text/html`, when nothing or only `*/*` is accepted.
 pub(crate) fn guess_accept_mime(req: &Request, default_type: Option<Mime>) -> Mime {
     let dmime: Mime = default_type.unwrap_or_else(|| "text/html".parse().unwrap());
-    let accept = req.accept();
-    accept
-        .first()
-        .unwrap_or(&dmime)
-        .to_string()
-        .parse()
-        .unwrap_or(dmime)
+    match req.accept().first() {
+        Some(mime) if mime.type_() != mime::STAR => {
+            mime.essence_str().parse().unwrap_or(dmime)
+        }
+        _ => dmime,
+    }
 }
 
 #[cfg(test)]
@@ -35,5 +37,26 @@ mod tests {
         headers.insert(ACCEPT, HeaderValue::from_static("application/javascript"));
         let mime = guess_accept_mime(&req, None);
         assert_eq!(mime, "application/javascript".parse::<Mime>().unwrap());
+
+        req.headers_mut().insert(
+            ACCEPT,
+            HeaderValue::from_static("text/html;q=0.5, application/xml;q=0.9, */*;q=0, text/plain"),
+        );
+        let accept = req.accept();
+        assert_eq!(accept.len(), 3);
+        assert_eq!(accept[1].essence_str(), "application/xml");
+        assert_eq!(
+            guess_accept_mime(&req, None),
+            "text/plain".parse::<Mime>().unwrap()
+        );
+
+        req.headers_mut().insert(
+            ACCEPT,
+            HeaderValue::from_static("*/*, application/json;q=0.5"),
+        );
+        assert_eq!(
+            guess_accept_mime(&req, None),
+            "text/html".parse::<Mime>().unwrap()
+        );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,315 @@
+//! Content negotiation over the `Accept`, `Accept-Language`, `Accept-Charset` and
+//! `Accept-Encoding` headers.
+//!
+//! The `parse_*` functions keep the header order. The `best_*` functions pick the entry of a
+//! server-supplied list with the highest quality, the first one in the list on a tie. The
+//! quality of an entry is the one of the most specific range matching it, so `text/html;q=0.1`
+//! wins over `*/*` for `text/html`.
+
+use mime::Mime;
+
+use crate::http::header::{HeaderName, ACCEPT, ACCEPT_CHARSET, ACCEPT_ENCODING, ACCEPT_LANGUAGE};
+use crate::http::Request;
+
+/// A value of an `Accept*` header with its `q` param, 1.0 if absent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityValue<T> {
+    pub value: T,
+    pub quality: f32,
+}
+
+/// Parses the lowercased tokens of `Accept-Language`, `Accept-Charset` or `Accept-Encoding`.
+///
+/// Entries with an invalid `q` are left out.
+pub fn parse_quality_values(header: &str) -> Vec<QualityValue<String>> {
+    header
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let value = params.next()?.trim().to_ascii_lowercase();
+            if value.is_empty() {
+                return None;
+            }
+            let quality = parse_quality(params)?;
+            Some(QualityValue { value, quality })
+        })
+        .collect()
+}
+
+/// Parses the media ranges of `Accept`, without their `q` param.
+pub fn parse_accept(header: &str) -> Vec<QualityValue<Mime>> {
+    header
+        .split(',')
+        .filter_map(|part| {
+            let (range, params) = part.split_once(';').unwrap_or((part, ""));
+            let quality = parse_quality(params.split(';'))?;
+            let params = params
+                .split(';')
+                .filter(|param| !is_quality(param) && !param.trim().is_empty())
+                .collect::<Vec<_>>();
+            let mut value = range.trim().to_owned();
+            for param in params {
+                value.push(';');
+                value.push_str(param.trim());
+            }
+            let value = value.parse().ok()?;
+            Some(QualityValue { value, quality })
+        })
+        .collect()
+}
+
+fn is_quality(param: &str) -> bool {
+    param
+        .split_once('=')
+        .map(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+        .unwrap_or(false)
+}
+
+fn parse_quality<'a>(params: impl Iterator<Item = &'a str>) -> Option<f32> {
+    let mut quality = 1.0;
+    for param in params {
+        if let Some((key, value)) = param.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("q") {
+                quality = value.trim().parse::<f32>().ok()?.clamp(0.0, 1.0);
+            }
+        }
+    }
+    Some(quality)
+}
+
+/// Quality of `item` given by the most specific accepted value `specificity` matches.
+fn quality_of<T, S: ?Sized>(
+    accepted: &[QualityValue<T>],
+    item: &S,
+    specificity: impl Fn(&T, &S) -> Option<usize>,
+) -> Option<f32> {
+    let mut best: Option<(usize, f32)> = None;
+    for accepted in accepted {
+        if let Some(s) = specificity(&accepted.value, item) {
+            if best.map(|(best_s, _)| s > best_s).unwrap_or(true) {
+                best = Some((s, accepted.quality));
+            }
+        }
+    }
+    best.map(|(_, quality)| quality)
+}
+
+fn best<'a, T, S: ?Sized>(
+    accepted: &[QualityValue<T>],
+    supported: impl IntoIterator<Item = &'a S>,
+    specificity: impl Fn(&T, &S) -> Option<usize>,
+) -> Option<&'a S> {
+    let mut best: Option<(&S, f32)> = None;
+    for item in supported {
+        let quality = quality_of(accepted, item, &specificity).unwrap_or(0.0);
+        if quality > 0.0 && best.map(|(_, q)| quality > q).unwrap_or(true) {
+            best = Some((item, quality));
+        }
+    }
+    best.map(|(item, _)| item)
+}
+
+fn media_range_specificity(range: &Mime, mime: &Mime) -> Option<usize> {
+    if range.type_() == mime::STAR {
+        Some(0)
+    } else if range.type_() != mime.type_() {
+        None
+    } else if range.subtype() == mime::STAR {
+        Some(1)
+    } else if range.essence_str() == mime.essence_str() {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn language_specificity(range: &str, tag: &str) -> Option<usize> {
+    if range == "*" {
+        return Some(0);
+    }
+    let matches = tag
+        .get(..range.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(range))
+        && matches!(tag.as_bytes().get(range.len()), None | Some(b'-'));
+    matches.then_some(range.len())
+}
+
+fn token_specificity(token: &str, item: &str) -> Option<usize> {
+    if token == "*" {
+        Some(0)
+    } else if token.eq_ignore_ascii_case(item) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// The accepted media type of `supported` with the highest quality.
+pub fn best_media_type<'a>(
+    accepted: &[QualityValue<Mime>],
+    supported: &'a [Mime],
+) -> Option<&'a Mime> {
+    best(accepted, supported, media_range_specificity)
+}
+
+/// The accepted language tag of `supported` with the highest quality, `en` ranges match `en-US`.
+pub fn best_language<'a>(
+    accepted: &[QualityValue<String>],
+    supported: &[&'a str],
+) -> Option<&'a str> {
+    best(
+        accepted,
+        supported.iter().copied(),
+        |range: &String, tag| language_specificity(range, tag),
+    )
+}
+
+/// The accepted charset or content coding of `supported` with the highest quality.
+pub fn best_token<'a>(accepted: &[QualityValue<String>], supported: &[&'a str]) -> Option<&'a str> {
+    best(
+        accepted,
+        supported.iter().copied(),
+        |token: &String, item| token_specificity(token, item),
+    )
+}
+
+/// Quality of a charset or content coding, `None` if neither it nor `*` is listed.
+pub fn token_quality(accepted: &[QualityValue<String>], token: &str) -> Option<f32> {
+    quality_of(accepted, token, |accepted: &String, token| {
+        token_specificity(accepted, token)
+    })
+}
+
+fn header<'a>(req: &'a Request, name: &HeaderName) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// The media type of `supported` to respond with, the first one if there is no `Accept` header.
+pub fn preferred_media_type<'a>(req: &Request, supported: &'a [Mime]) -> Option<&'a Mime> {
+    match header(req, &ACCEPT) {
+        Some(accept) => best_media_type(&parse_accept(accept), supported),
+        None => supported.first(),
+    }
+}
+
+/// Like `preferred_media_type` for `Accept-Language`.
+pub fn preferred_language<'a>(req: &Request, supported: &[&'a str]) -> Option<&'a str> {
+    match header(req, &ACCEPT_LANGUAGE) {
+        Some(accept) => best_language(&parse_quality_values(accept), supported),
+        None => supported.first().copied(),
+    }
+}
+
+/// Like `preferred_media_type` for `Accept-Charset`.
+pub fn preferred_charset<'a>(req: &Request, supported: &[&'a str]) -> Option<&'a str> {
+    match header(req, &ACCEPT_CHARSET) {
+        Some(accept) => best_token(&parse_quality_values(accept), supported),
+        None => supported.first().copied(),
+    }
+}
+
+/// Like `preferred_media_type` for `Accept-Encoding`.
+pub fn preferred_encoding<'a>(req: &Request, supported: &[&'a str]) -> Option<&'a str> {
+    match header(req, &ACCEPT_ENCODING) {
+        Some(accept) => best_token(&parse_quality_values(accept), supported),
+        None => supported.first().copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::TestClient;
+
+    fn mimes(list: &[&str]) -> Vec<Mime> {
+        list.iter().map(|m| m.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse_quality_values() {
+        let accepted = parse_quality_values("gzip;q=0.8, BR , *;q=0.1, identity; q=0, x;q=y");
+        let accepted = accepted
+            .into_iter()
+            .map(|v| (v.value, v.quality))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accepted,
+            vec![
+                ("gzip".to_owned(), 0.8),
+                ("br".to_owned(), 1.0),
+                ("*".to_owned(), 0.1),
+                ("identity".to_owned(), 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_best_media_type() {
+        let accepted = parse_accept("text/*;q=0.5, text/html;level=1;q=0.2, */*;q=0.1, a/b;q=x");
+        assert_eq!(accepted.len(), 3);
+        assert_eq!(accepted[1].value.essence_str(), "text/html");
+        assert_eq!(accepted[1].value.get_param("level").unwrap(), "1");
+        assert!(accepted[1].value.get_param("q").is_none());
+
+        let supported = mimes(&["text/html", "application/json", "text/plain"]);
+        assert_eq!(
+            best_media_type(&accepted, &supported)
+                .unwrap()
+                .essence_str(),
+            "text/plain"
+        );
+        let accepted = parse_accept("application/json;q=0.9, */*;q=0.9");
+        assert_eq!(
+            best_media_type(&accepted, &supported)
+                .unwrap()
+                .essence_str(),
+            "text/html"
+        );
+        let accepted = parse_accept("application/problem+json, application/*;q=0");
+        let supported = mimes(&["application/json", "application/problem+json"]);
+        assert_eq!(
+            best_media_type(&accepted, &supported)
+                .unwrap()
+                .essence_str(),
+            "application/problem+json"
+        );
+        assert_eq!(best_media_type(&parse_accept("image/*"), &supported), None);
+    }
+
+    #[test]
+    fn test_best_language() {
+        let accepted = parse_quality_values("fr-CH, fr;q=0.9, en;q=0.8, de;q=0.7, *;q=0.5");
+        assert_eq!(best_language(&accepted, &["en-US", "fr"]), Some("fr"));
+        assert_eq!(best_language(&accepted, &["en-US", "de"]), Some("en-US"));
+        assert_eq!(best_language(&accepted, &["es", "english"]), Some("es"));
+        let accepted = parse_quality_values("en, *;q=0");
+        assert_eq!(best_language(&accepted, &["es", "EN-gb"]), Some("EN-gb"));
+        assert_eq!(best_language(&accepted, &["es"]), None);
+    }
+
+    #[test]
+    fn test_preferred() {
+        let req = TestClient::get("http://127.0.0.1:7878/")
+            .add_header(ACCEPT_CHARSET, "iso-8859-5, utf-8;q=0.5", true)
+            .add_header(ACCEPT_ENCODING, "gzip;q=0.5, *;q=0.7", true)
+            .build();
+        assert_eq!(
+            preferred_charset(&req, &["utf-8", "iso-8859-5"]),
+            Some("iso-8859-5")
+        );
+        assert_eq!(preferred_encoding(&req, &["gzip", "br"]), Some("br"));
+        assert_eq!(preferred_language(&req, &["en", "fr"]), Some("en"));
+        assert_eq!(
+            preferred_media_type(&req, &mimes(&["text/plain"]))
+                .unwrap()
+                .essence_str(),
+            "text/plain"
+        );
+        assert_eq!(
+            token_quality(&parse_quality_values("*;q=0.7"), "gzip"),
+            Some(0.7)
+        );
+    }
+}
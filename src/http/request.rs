@@ -6,7 +6,7 @@ use http::header::{self, AsHeaderName, HeaderMap, HeaderValue, IntoHeaderName};
 use http::method::Method;
 pub use http::request::Parts;
 use hyper::http::version::Version;
-use hyper::http::{self, Extensions, Uri};
+use hyper::http::{self, uri::Scheme, Extensions, Uri};
 pub use hyper::Body as ReqBody;
 use multimap::MultiMap;
 use once_cell::sync::OnceCell;
@@ -15,7 +15,7 @@ use serde::de::Deserialize;
 use crate::addr::SocketAddr;
 use crate::extract::{Extractible, Metadata};
 use crate::http::form::{FilePart, FormData};
-use crate::http::{Mime, ParseError};
+use crate::http::{negotiation, Mime, ParseError};
 use crate::serde::{
     from_request, from_str_map, from_str_multi_map, from_str_multi_val, from_str_val,
 };
@@ -38,6 +38,9 @@ pub struct Request {
     /// Http protocol version
     version: Version,
     pub(crate) remote_addr: Option<SocketAddr>,
+    pub(crate) scheme: Scheme,
+    pub(crate) matched_route: Option<String>,
+    pub(crate) matched_names: Vec<String>,
 }
 
 impl fmt::Debug for Request {
@@ -100,6 +103,9 @@ impl From<hyper::Request<ReqBody>> for Request {
             payload: tokio::sync::OnceCell::new(),
             version,
             remote_addr: None,
+            scheme: Scheme::HTTP,
+            matched_route: None,
+            matched_names: Vec::new(),
         }
     }
 }
@@ -120,6 +126,9 @@ impl Request {
             payload: tokio::sync::OnceCell::new(),
             version: Version::default(),
             remote_addr: None,
+            scheme: Scheme::HTTP,
+            matched_route: None,
+            matched_names: Vec::new(),
         }
     }
     pub fn uri(&self) -> &Uri {
@@ -134,6 +143,19 @@ impl Request {
     pub fn remote_addr(&self) -> Option<&SocketAddr> {
         self.remote_addr.as_ref()
     }
+    /// Scheme of the connection the request came in on, `https` behind a TLS listener. Unlike
+    /// `uri().scheme()`, it is also known for requests in origin-form.
+    pub fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+    /// Path patterns of the routers that matched the request, e.g. `/users/<id:num>`.
+    pub fn matched_route(&self) -> Option<&str> {
+        self.matched_route.as_deref()
+    }
+    /// Names of the routers that matched the request, outermost first.
+    pub fn matched_names(&self) -> &[String] {
+        &self.matched_names
+    }
     pub fn headers(&self) -> &HeaderMap {
         &self.headers
     }
@@ -199,17 +221,18 @@ impl Request {
     pub fn extensions_mut(&mut self) -> &mut Extensions {
         &mut self.extensions
     }
+    /// Media types of the `Accept` header, highest q-value first and in header order on a tie.
+    /// Types with `q=0` are left out.
+    ///
+    /// Use `negotiation::preferred_media_type` to pick one of the types a handler supports.
     pub fn accept(&self) -> Vec<Mime> {
-        let mut list: Vec<Mime> = vec![];
-        if let Some(accept) = self.headers.get("accept").and_then(|h| h.to_str().ok()) {
-            let parts: Vec<&str> = accept.split(',').collect();
-            for part in parts {
-                if let Ok(mt) = part.parse() {
-                    list.push(mt);
-                }
-            }
-        }
-        list
+        let mut list = match self.headers.get("accept").and_then(|h| h.to_str().ok()) {
+            Some(accept) => negotiation::parse_accept(accept),
+            None => vec![],
+        };
+        list.retain(|v| v.quality > 0.0);
+        list.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+        list.into_iter().map(|v| v.value).collect()
     }
     pub fn first_accept(&self) -> Option<Mime> {
         let mut accept = self.accept();
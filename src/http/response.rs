@@ -246,7 +246,8 @@ impl Response {
         match body {
             ResBody::None => {
                 res.headers_mut()
-                    .insert(CONTENT_LENGTH, HeaderValue::from_static("0"));
+                    .entry(CONTENT_LENGTH)
+                    .or_insert(HeaderValue::from_static("0"));
             }
             ResBody::Once(bytes) => {
                 *res.body_mut() = hyper::Body::from(bytes);
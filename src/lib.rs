@@ -11,7 +11,9 @@ pub mod addr;
 pub mod catcher;
 mod depot;
 mod error;
+pub mod extra;
 pub mod extract;
+pub mod fs;
 pub mod handler;
 pub mod http;
 pub mod listener;
@@ -31,7 +33,7 @@ pub use self::handler::Handler;
 pub use self::http::{Request, Response};
 pub use self::listener::Listener;
 pub use self::routing::{FlowCtrl, Router};
-pub use self::server::Server;
+pub use self::server::{Server, ServerHandle};
 pub use self::service::Service;
 pub use self::writer::{Piece, Writer};
 /// Result type which has salvo::Error as it's error type.
@@ -48,7 +50,7 @@ pub mod prelude {
     pub use crate::handler::{empty_handler, Handler};
     pub use crate::listener::{JoinedListener, Listener, TcpListener};
     pub use crate::routing::{FlowCtrl, Router};
-    pub use crate::server::Server;
+    pub use crate::server::{Server, ServerHandle};
     pub use crate::service::Service;
     pub use crate::writer::{Json, Piece, Redirect, Text, Writer};
 }
@@ -11,6 +11,13 @@ use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use crate::addr::SocketAddr;
 use crate::transport::Transport;
 
+pub mod rustls;
+pub use self::rustls::{Keycert, RustlsConfig, RustlsListener};
+#[cfg(unix)]
+pub mod unix;
+#[cfg(unix)]
+pub use self::unix::UnixListener;
+
 pub trait Listener: Accept {
     fn join<T>(self, other: T) -> JoinedListener<Self, T>
     where
@@ -88,6 +95,12 @@ where
             JoinedStream::B(stream) => stream.remote_addr(),
         }
     }
+    fn scheme(&self) -> crate::http::uri::Scheme {
+        match self {
+            JoinedStream::A(stream) => stream.scheme(),
+            JoinedStream::B(stream) => stream.scheme(),
+        }
+    }
 }
 
 pub struct JoinedListener<A, B> {
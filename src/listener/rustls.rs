@@ -0,0 +1,488 @@
+use std::fs::File;
+use std::future::{ready, Future, Ready};
+use std::io::{self, BufReader, Cursor, Error as IoError, Read};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_util::stream::{once, Fuse, Once};
+use futures_util::{ready, FutureExt, Stream, StreamExt};
+use hyper::server::accept::Accept;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use super::{IntoAddrIncoming, Listener, TcpListener};
+use crate::addr::SocketAddr;
+use crate::http::uri::Scheme;
+use crate::transport::Transport;
+
+/// Private key and certificate chain, either read from PEM files or given in memory.
+#[derive(Clone, Debug, Default)]
+pub struct Keycert {
+    key_path: Option<PathBuf>,
+    key: Vec<u8>,
+    cert_path: Option<PathBuf>,
+    cert: Vec<u8>,
+}
+
+impl Keycert {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_key_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.key_path = Some(path.as_ref().into());
+        self
+    }
+    pub fn with_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.key = key.into();
+        self
+    }
+    pub fn with_cert_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.cert_path = Some(path.as_ref().into());
+        self
+    }
+    pub fn with_cert(mut self, cert: impl Into<Vec<u8>>) -> Self {
+        self.cert = cert.into();
+        self
+    }
+
+    pub fn key(&mut self) -> io::Result<&[u8]> {
+        if self.key.is_empty() {
+            if let Some(path) = &self.key_path {
+                File::open(path)?.read_to_end(&mut self.key)?;
+            }
+        }
+        if self.key.is_empty() {
+            Err(IoError::other("empty key"))
+        } else {
+            Ok(&self.key)
+        }
+    }
+    pub fn cert(&mut self) -> io::Result<&[u8]> {
+        if self.cert.is_empty() {
+            if let Some(path) = &self.cert_path {
+                File::open(path)?.read_to_end(&mut self.cert)?;
+            }
+        }
+        if self.cert.is_empty() {
+            Err(IoError::other("empty cert"))
+        } else {
+            Ok(&self.cert)
+        }
+    }
+}
+
+/// Builder for rustls `ServerConfig`, defaults to ALPN `h2` and `http/1.1`.
+#[derive(Clone, Debug)]
+pub struct RustlsConfig {
+    keycert: Keycert,
+    alpn_protocols: Vec<Vec<u8>>,
+}
+
+impl RustlsConfig {
+    pub fn new(keycert: Keycert) -> Self {
+        RustlsConfig {
+            keycert,
+            alpn_protocols: vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
+    }
+    pub fn with_alpn_protocols(mut self, alpn_protocols: Vec<Vec<u8>>) -> Self {
+        self.alpn_protocols = alpn_protocols;
+        self
+    }
+    pub fn alpn_protocols(&self) -> &[Vec<u8>] {
+        &self.alpn_protocols
+    }
+
+    pub fn build_server_config(mut self) -> io::Result<ServerConfig> {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(Cursor::new(self.keycert.cert()?)))
+            .map_err(|_| IoError::other("failed to parse tls certificates"))?
+            .into_iter()
+            .map(Certificate)
+            .collect::<Vec<_>>();
+        if certs.is_empty() {
+            return Err(IoError::other("no tls certificates found"));
+        }
+
+        let mut key_reader = BufReader::new(Cursor::new(self.keycert.key()?));
+        let key = loop {
+            match rustls_pemfile::read_one(&mut key_reader)
+                .map_err(|_| IoError::other("failed to parse tls private key"))?
+            {
+                Some(rustls_pemfile::Item::PKCS8Key(key))
+                | Some(rustls_pemfile::Item::RSAKey(key))
+                | Some(rustls_pemfile::Item::ECKey(key)) => break PrivateKey(key),
+                Some(_) => continue,
+                None => return Err(IoError::other("no tls private key found")),
+            }
+        };
+
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| IoError::other(e.to_string()))?;
+        config.alpn_protocols = self.alpn_protocols;
+        Ok(config)
+    }
+}
+
+/// Anything that can feed `RustlsConfig`s to a `RustlsListener`, a single config or a stream of them.
+pub trait IntoConfigStream<C> {
+    type Stream: Stream<Item = C> + Send + Unpin + 'static;
+
+    fn into_stream(self) -> Self::Stream;
+}
+
+impl IntoConfigStream<RustlsConfig> for RustlsConfig {
+    type Stream = Once<Ready<RustlsConfig>>;
+
+    fn into_stream(self) -> Self::Stream {
+        once(ready(self))
+    }
+}
+
+impl<T> IntoConfigStream<RustlsConfig> for T
+where
+    T: Stream<Item = RustlsConfig> + Send + Unpin + 'static,
+{
+    type Stream = T;
+
+    fn into_stream(self) -> Self::Stream {
+        self
+    }
+}
+
+/// Listener which wraps an inner `Accept` and serves TLS over it.
+///
+/// Each config yielded by the config stream replaces the current one, so new
+/// connections pick up reloaded certificates while established ones keep theirs.
+/// Configs are built on the blocking thread pool, as reading key and cert files blocks.
+pub struct RustlsListener<C, A> {
+    config_stream: Fuse<C>,
+    /// The config being built from the last one yielded, reading its files blocks.
+    building: Option<JoinHandle<io::Result<ServerConfig>>>,
+    acceptor: Option<TlsAcceptor>,
+    inner: A,
+}
+
+impl<C> RustlsListener<C, TcpListener>
+where
+    C: Stream<Item = RustlsConfig> + Send + Unpin + 'static,
+{
+    pub fn bind<T>(config: T, incoming: impl IntoAddrIncoming) -> Self
+    where
+        T: IntoConfigStream<RustlsConfig, Stream = C>,
+    {
+        Self::try_bind(config, incoming).unwrap()
+    }
+    pub fn try_bind<T>(config: T, incoming: impl IntoAddrIncoming) -> Result<Self, hyper::Error>
+    where
+        T: IntoConfigStream<RustlsConfig, Stream = C>,
+    {
+        Ok(Self::new(config, TcpListener::try_bind(incoming)?))
+    }
+    pub fn local_addr(&self) -> std::net::SocketAddr {
+        self.inner.local_addr()
+    }
+}
+
+impl<C, A> RustlsListener<C, A>
+where
+    C: Stream<Item = RustlsConfig> + Send + Unpin + 'static,
+{
+    pub fn new<T>(config: T, inner: A) -> Self
+    where
+        T: IntoConfigStream<RustlsConfig, Stream = C>,
+    {
+        RustlsListener {
+            config_stream: config.into_stream().fuse(),
+            building: None,
+            acceptor: None,
+            inner,
+        }
+    }
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<C, A> Listener for RustlsListener<C, A>
+where
+    C: Stream<Item = RustlsConfig> + Send + Unpin + 'static,
+    A: Accept + Send + Unpin + 'static,
+    A::Conn: Transport + Send + Unpin + 'static,
+    A::Error: Into<IoError>,
+{
+}
+impl<C, A> Accept for RustlsListener<C, A>
+where
+    C: Stream<Item = RustlsConfig> + Send + Unpin + 'static,
+    A: Accept + Send + Unpin + 'static,
+    A::Conn: Transport + Send + Unpin + 'static,
+    A::Error: Into<IoError>,
+{
+    type Conn = RustlsStream<A::Conn>;
+    type Error = IoError;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+        // Configs are built one at a time in order, the current acceptor is kept meanwhile.
+        loop {
+            if let Some(building) = &mut this.building {
+                let built = match building.poll_unpin(cx) {
+                    Poll::Ready(built) => built,
+                    Poll::Pending => break,
+                };
+                this.building = None;
+                match built {
+                    Ok(Ok(config)) => {
+                        this.acceptor = Some(TlsAcceptor::from(Arc::new(config)));
+                        tracing::info!("tls config loaded");
+                    }
+                    Ok(Err(e)) => tracing::error!(error = ?e, "invalid tls config"),
+                    Err(e) => tracing::error!(error = ?e, "building tls config failed"),
+                }
+            }
+            match this.config_stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(config)) => {
+                    this.building = Some(tokio::task::spawn_blocking(move || {
+                        config.build_server_config()
+                    }));
+                }
+                _ => break,
+            }
+        }
+        let acceptor = match &this.acceptor {
+            Some(acceptor) => acceptor.clone(),
+            // Without any config to come, no connection can ever be accepted.
+            None if this.config_stream.is_done() && this.building.is_none() => {
+                tracing::error!("tls config stream ended without a valid config");
+                return Poll::Ready(None);
+            }
+            None => return Poll::Pending,
+        };
+        match ready!(Pin::new(&mut this.inner).poll_accept(cx)) {
+            Some(Ok(conn)) => Poll::Ready(Some(Ok(RustlsStream::new(conn, acceptor)))),
+            Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+enum RustlsState<S> {
+    Handshaking(tokio_rustls::Accept<S>),
+    Streaming(TlsStream<S>),
+}
+
+/// TLS connection, the handshake is driven by the first read or write.
+pub struct RustlsStream<S> {
+    remote_addr: Option<SocketAddr>,
+    state: RustlsState<S>,
+}
+
+impl<S> RustlsStream<S>
+where
+    S: Transport + Unpin,
+{
+    fn new(stream: S, acceptor: TlsAcceptor) -> Self {
+        RustlsStream {
+            remote_addr: stream.remote_addr(),
+            state: RustlsState::Handshaking(acceptor.accept(stream)),
+        }
+    }
+}
+
+impl<S> Transport for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr.clone()
+    }
+    fn scheme(&self) -> Scheme {
+        Scheme::HTTPS
+    }
+}
+
+impl<S> AsyncRead for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let pin = self.get_mut();
+        match &mut pin.state {
+            RustlsState::Handshaking(accept) => match ready!(Pin::new(accept).poll(cx)) {
+                Ok(mut stream) => {
+                    let result = Pin::new(&mut stream).poll_read(cx, buf);
+                    pin.state = RustlsState::Streaming(stream);
+                    result
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            },
+            RustlsState::Streaming(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<S> AsyncWrite for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let pin = self.get_mut();
+        match &mut pin.state {
+            RustlsState::Handshaking(accept) => match ready!(Pin::new(accept).poll(cx)) {
+                Ok(mut stream) => {
+                    let result = Pin::new(&mut stream).poll_write(cx, buf);
+                    pin.state = RustlsState::Streaming(stream);
+                    result
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            },
+            RustlsState::Streaming(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().state {
+            RustlsState::Handshaking(_) => Poll::Ready(Ok(())),
+            RustlsState::Streaming(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().state {
+            RustlsState::Handshaking(_) => Poll::Ready(Ok(())),
+            RustlsState::Streaming(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[handler(internal)]
+    async fn hello() -> &'static str {
+        "Hello World"
+    }
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rustls_listener() {
+        let config = RustlsConfig::new(
+            Keycert::new()
+                .with_cert_path("certs/cert.pem")
+                .with_key_path("certs/key.pem"),
+        );
+        let listener = RustlsListener::bind(config, "127.0.0.1:0");
+        let addr = listener.local_addr();
+        tokio::spawn(async move {
+            Server::new(listener).serve(Router::new().get(hello)).await;
+        });
+
+        let result = client()
+            .get(format!("https://localhost:{}", addr.port()))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(result, "Hello World");
+    }
+
+    #[tokio::test]
+    async fn test_rustls_config_reload() {
+        let keycert = Keycert::new()
+            .with_cert(include_bytes!("../../certs/cert.pem").as_ref())
+            .with_key(include_bytes!("../../certs/key.pem").as_ref());
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let listener = RustlsListener::bind(rx, "127.0.0.1:0");
+        let addr = listener.local_addr();
+        tokio::spawn(async move {
+            Server::new(listener).serve(Router::new().get(hello)).await;
+        });
+
+        tx.unbounded_send(RustlsConfig::new(keycert.clone()))
+            .unwrap();
+        let result = client()
+            .get(format!("https://localhost:{}", addr.port()))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(result, "Hello World");
+
+        tx.unbounded_send(RustlsConfig::new(Keycert::new().with_cert("invalid")))
+            .unwrap();
+        tx.unbounded_send(
+            RustlsConfig::new(keycert).with_alpn_protocols(vec![b"http/1.1".to_vec()]),
+        )
+        .unwrap();
+        let result = client()
+            .get(format!("https://localhost:{}", addr.port()))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(result, "Hello World");
+    }
+
+    #[tokio::test]
+    async fn test_rustls_without_valid_config() {
+        let config = RustlsConfig::new(Keycert::new().with_cert("invalid"));
+        let mut listener = RustlsListener::bind(config, "127.0.0.1:0");
+        for _ in 0..2 {
+            let conn =
+                futures_util::future::poll_fn(|cx| Pin::new(&mut listener).poll_accept(cx)).await;
+            assert!(conn.is_none());
+        }
+    }
+
+    #[test]
+    fn test_build_server_config() {
+        let config = RustlsConfig::new(
+            Keycert::new()
+                .with_cert(include_bytes!("../../certs/cert.pem").as_ref())
+                .with_key(include_bytes!("../../certs/key.pem").as_ref()),
+        )
+        .build_server_config()
+        .unwrap();
+        assert_eq!(
+            config.alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+
+        assert!(RustlsConfig::new(Keycert::new().with_cert("nothing"))
+            .build_server_config()
+            .is_err());
+    }
+}
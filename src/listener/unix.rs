@@ -0,0 +1,155 @@
+use std::fs::{self, Permissions};
+use std::io::{self, Error as IoError};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::ready;
+use hyper::server::accept::Accept;
+use tokio::net::{UnixListener as TokioUnixListener, UnixStream};
+
+use super::Listener;
+
+/// Listener on a Unix domain socket.
+///
+/// A stale socket file left at the path is replaced on bind, and the file is
+/// removed again when the listener is dropped.
+pub struct UnixListener {
+    incoming: TokioUnixListener,
+    path: PathBuf,
+}
+
+impl UnixListener {
+    pub fn incoming(&self) -> &TokioUnixListener {
+        &self.incoming
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    pub fn bind(path: impl AsRef<Path>) -> Self {
+        Self::try_bind(path).unwrap()
+    }
+    pub fn try_bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if metadata.file_type().is_socket() {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(UnixListener {
+            incoming: TokioUnixListener::bind(path)?,
+            path: path.to_owned(),
+        })
+    }
+    /// Change the permissions of the socket file, e.g. to let a proxy running as another user connect.
+    pub fn set_permissions(&self, permissions: Permissions) -> io::Result<()> {
+        fs::set_permissions(&self.path, permissions)
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            tracing::warn!(error = ?e, path = ?self.path, "failed to remove unix socket file");
+        }
+    }
+}
+
+impl Listener for UnixListener {}
+impl Accept for UnixListener {
+    type Conn = UnixStream;
+    type Error = IoError;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let (stream, _) = ready!(self.incoming.poll_accept(cx))?;
+        Poll::Ready(Some(Ok(stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use futures_util::{Stream, StreamExt};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::listener::{Listener, TcpListener};
+    use crate::transport::Transport;
+
+    impl Stream for UnixListener {
+        type Item = Result<UnixStream, IoError>;
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.poll_accept(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unix_listener() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("salvo.sock");
+
+        let mut listener = UnixListener::bind(&path);
+        listener
+            .set_permissions(Permissions::from_mode(0o660))
+            .unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o660
+        );
+        let client_path = path.clone();
+        tokio::spawn(async move {
+            let mut stream = UnixStream::connect(client_path).await.unwrap();
+            stream.write_i32(150).await.unwrap();
+        });
+
+        let mut stream = listener.next().await.unwrap().unwrap();
+        assert!(stream.remote_addr().unwrap().is_unix());
+        assert_eq!(stream.read_i32().await.unwrap(), 150);
+
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_unix_listener_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("salvo.sock");
+
+        let stale = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(stale);
+        assert!(path.exists());
+        let _listener = UnixListener::bind(&path);
+
+        let file = dir.path().join("regular");
+        fs::write(&file, "data").unwrap();
+        assert!(UnixListener::try_bind(&file).is_err());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "data");
+    }
+
+    #[tokio::test]
+    async fn test_joined_unix_listener() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("salvo.sock");
+        let addr = std::net::SocketAddr::from(([127, 0, 0, 1], 6980));
+
+        let mut listener = TcpListener::bind(addr).join(UnixListener::bind(&path));
+        tokio::spawn(async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_i32(50).await.unwrap();
+
+            let mut stream = UnixStream::connect(path).await.unwrap();
+            stream.write_i32(100).await.unwrap();
+        });
+        let mut stream = listener.next().await.unwrap().unwrap();
+        let first = stream.read_i32().await.unwrap();
+        let mut stream = listener.next().await.unwrap().unwrap();
+        let second = stream.read_i32().await.unwrap();
+        assert_eq!(first + second, 150);
+    }
+}
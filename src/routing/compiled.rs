@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{DetectMatched, PathState, Router};
+use crate::http::{Method, Request};
+use crate::Handler;
+
+/// Characters that make a path segment something else than a `ConstWisp`.
+const WISP_CHARS: &[char] = &['<', '>', '[', ']', '(', ')', ':'];
+
+/// A `Router` tree frozen with an index over the children of every router, so `detect` only
+/// runs the filters of children that can match the request.
+///
+/// Children are indexed by the leading constant segments of their first `PathFilter` and by
+/// their first `MethodFilter`. Children starting with any other filter, e.g. a regex wisp or a
+/// `FnFilter`, are always tried. The result is the same as `Router::detect`, and so are the
+/// allowed methods, hoops and catchers recorded in the `PathState`: candidates and children for
+/// other methods are tried together in the order they were pushed.
+pub struct CompiledRouter {
+    router: Arc<Router>,
+    root: Node,
+}
+
+struct Node {
+    children: Vec<Node>,
+    index: ChildIndex,
+    endpoint: Endpoint,
+}
+
+/// What a `DetectMatched` holds when the node's own handler matches.
+#[derive(Default)]
+struct Endpoint {
+    hoops: Vec<Arc<dyn Handler>>,
+    route: String,
+    names: Vec<String>,
+    trailing_slash: bool,
+}
+
+#[derive(Default)]
+struct ChildIndex {
+    segments: SegmentTrie,
+    methods: HashMap<Method, Vec<usize>>,
+    others: Vec<usize>,
+}
+
+#[derive(Default)]
+struct SegmentTrie {
+    children: HashMap<String, SegmentTrie>,
+    routers: Vec<usize>,
+}
+
+impl CompiledRouter {
+    pub fn new(router: impl Into<Arc<Router>>) -> Self {
+        let router = router.into();
+        let root = Node::new(&router, &Endpoint::default());
+        CompiledRouter { router, root }
+    }
+    pub fn router(&self) -> &Arc<Router> {
+        &self.router
+    }
+    pub fn detect(&self, req: &mut Request, path_state: &mut PathState) -> Option<DetectMatched> {
+        self.root.detect(&self.router, req, path_state)
+    }
+}
+
+impl Node {
+    fn new(router: &Router, parent: &Endpoint) -> Self {
+        let mut endpoint = Endpoint {
+            hoops: [&parent.hoops[..], &router.hoops[..]].concat(),
+            route: parent.route.clone(),
+            names: parent.names.clone(),
+            trailing_slash: parent.trailing_slash || router.requires_trailing_slash(),
+        };
+        router.push_route(&mut endpoint.route);
+        endpoint.names.extend(router.name.iter().cloned());
+
+        let mut index = ChildIndex::default();
+        for (i, child) in router.routers.iter().enumerate() {
+            index.insert(i, child);
+        }
+        let children = router
+            .routers
+            .iter()
+            .map(|child| Node::new(child, &endpoint))
+            .collect();
+        if endpoint.route.is_empty() {
+            endpoint.route.push('/');
+        }
+        Node {
+            children,
+            index,
+            endpoint,
+        }
+    }
+
+    fn detect(
+        &self,
+        router: &Router,
+        req: &mut Request,
+        path_state: &mut PathState,
+    ) -> Option<DetectMatched> {
+        if !router.run_filters(req, path_state) {
+            return None;
+        }
+        let chain_lens = path_state.enter_router(router);
+        let dm = self.detect_inner(router, req, path_state);
+        path_state.leave_router(chain_lens, dm.is_some());
+        dm
+    }
+    fn detect_inner(
+        &self,
+        router: &Router,
+        req: &mut Request,
+        path_state: &mut PathState,
+    ) -> Option<DetectMatched> {
+        let original_cursor = path_state.cursor;
+        if !router.routers.is_empty() {
+            let candidates = self
+                .index
+                .candidates(path_state)
+                .unwrap_or_else(|| (0..router.routers.len()).collect());
+            for i in candidates {
+                if let Some(dm) = self.children[i].detect(&router.routers[i], req, path_state) {
+                    return Some(dm);
+                } else {
+                    path_state.cursor = original_cursor;
+                }
+            }
+        }
+
+        if let Some(handler) = &router.handler {
+            if path_state.ended() {
+                return Some(DetectMatched {
+                    hoops: self.endpoint.hoops.clone(),
+                    handler: handler.clone(),
+                    route: self.endpoint.route.clone(),
+                    names: self.endpoint.names.clone(),
+                    trailing_slash: self.endpoint.trailing_slash,
+                });
+            }
+        }
+        None
+    }
+}
+
+impl ChildIndex {
+    fn insert(&mut self, i: usize, child: &Router) {
+        let first = child.filters.first();
+        if let Some(filter) = first.and_then(|f| f.as_path_filter()) {
+            let segments = const_segments(filter.raw_value());
+            if !segments.is_empty() {
+                let mut trie = &mut self.segments;
+                for segment in segments {
+                    trie = trie.children.entry(segment.to_owned()).or_default();
+                }
+                trie.routers.push(i);
+                return;
+            }
+        }
+        if let Some(filter) = first.and_then(|f| f.as_method_filter()) {
+            self.methods.entry(filter.0.clone()).or_default().push(i);
+            return;
+        }
+        self.others.push(i);
+    }
+
+    /// Indexes of the children that may match, in the order they were pushed.
+    ///
+    /// `None` when the cursor stands inside a segment, then every child has to be tried.
+    fn candidates(&self, path_state: &PathState) -> Option<Vec<usize>> {
+        let (row, col) = path_state.cursor;
+        let rest = match path_state.parts.get(row) {
+            None => &[][..],
+            Some(_) if col == 0 => &path_state.parts[row..],
+            Some(part) if col >= part.len() => &path_state.parts[row + 1..],
+            Some(_) => return None,
+        };
+        let mut candidates = self.others.clone();
+        // Children for other methods can not match, they are tried to record the allowed methods.
+        for routers in self.methods.values() {
+            candidates.extend(routers);
+        }
+        let mut trie = &self.segments;
+        for part in rest {
+            match trie.children.get(part) {
+                Some(child) => {
+                    candidates.extend(&child.routers);
+                    trie = child;
+                }
+                None => break,
+            }
+        }
+        candidates.sort_unstable();
+        Some(candidates)
+    }
+}
+
+/// Leading segments of a path pattern that are parsed into a single `ConstWisp` each.
+fn const_segments(pattern: &str) -> Vec<&str> {
+    pattern
+        .split('/')
+        .map(|s| s.trim_start_matches([' ', '\t']))
+        .filter(|s| !s.is_empty())
+        .take_while(|s| !s.contains(WISP_CHARS))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler;
+    use crate::routing::{filter, Filter};
+    use crate::test::TestClient;
+
+    #[handler(internal)]
+    async fn fake_handler() {}
+
+    #[test]
+    fn test_const_segments() {
+        assert_eq!(const_segments("/api/v1/users/<id>"), ["api", "v1", "users"]);
+        assert_eq!(const_segments("api//v<n:num>/x"), ["api"]);
+        assert!(const_segments("<id>/users").is_empty());
+        assert!(const_segments("/").is_empty());
+    }
+
+    /// Detects with both the compiled and the plain router and checks they agree.
+    fn detect_both(router: &Arc<Router>, method: &str, url: &str) -> Option<String> {
+        let compiled = CompiledRouter::new(router.clone());
+        let mut results = Vec::with_capacity(2);
+        for use_index in [false, true] {
+            let mut req = TestClient::get(url).build();
+            *req.method_mut() = Method::from_bytes(method.as_bytes()).unwrap();
+            let mut path_state = PathState::new(req.uri().path());
+            let dm = if use_index {
+                compiled.detect(&mut req, &mut path_state)
+            } else {
+                router.detect(&mut req, &mut path_state)
+            };
+            let catchers = path_state
+                .catchers
+                .iter()
+                .map(|c| Arc::as_ptr(c) as *const () as usize)
+                .collect::<Vec<_>>();
+            let recorded = (
+                path_state.allowed_methods.clone(),
+                path_state.hoops.len(),
+                catchers,
+            );
+            let dm = dm.map(|dm| (dm.route, dm.names, dm.hoops.len(), path_state.params));
+            results.push((dm, recorded));
+        }
+        assert_eq!(results[0], results[1], "{} {}", method, url);
+        results.pop().unwrap().0.map(|(route, ..)| route)
+    }
+
+    #[test]
+    fn test_compiled_detect() {
+        let router = Arc::new(
+            Router::with_hoop(fake_handler).push(
+                Router::with_path("api")
+                    .name("api")
+                    .hoop(fake_handler)
+                    .push(Router::with_path("users/<id:num>").get(fake_handler))
+                    .push(
+                        Router::with_path("users/new")
+                            .name("users.new")
+                            .get(fake_handler),
+                    )
+                    .push(Router::with_path("users/<id>/posts").post(fake_handler))
+                    .push(Router::with_path("<**rest>").handle(fake_handler))
+                    .push(Router::with_path("files/<*path>").get(fake_handler))
+                    .push(
+                        Router::with_filter_fn(|req, _| req.query::<String>("fn").is_some())
+                            .handle(fake_handler),
+                    )
+                    .push(Router::with_filter(filter::get()).handle(fake_handler))
+                    .push(Router::new().delete(fake_handler)),
+            ),
+        );
+        let cases = [
+            (
+                "GET",
+                "http://local.host/api/users/7",
+                Some("/api/users/<id:num>"),
+            ),
+            (
+                "GET",
+                "http://local.host/api/users/new",
+                Some("/api/users/new"),
+            ),
+            (
+                "POST",
+                "http://local.host/api/users/x/posts",
+                Some("/api/users/<id>/posts"),
+            ),
+            (
+                "GET",
+                "http://local.host/api/files/a/b",
+                Some("/api/<**rest>"),
+            ),
+            ("GET", "http://local.host/api", Some("/api/<**rest>")),
+            ("GET", "http://local.host/other", None),
+            ("GET", "http://local.host/", None),
+        ];
+        for (method, url, route) in cases {
+            assert_eq!(detect_both(&router, method, url).as_deref(), route);
+        }
+
+        let router = Arc::new(
+            Router::new()
+                .push(
+                    Router::with_path("users/new")
+                        .name("users.new")
+                        .get(fake_handler),
+                )
+                .push(Router::with_path("users/<id>").get(fake_handler))
+                .push(Router::with_path("users").get(fake_handler))
+                .push(
+                    Router::with_filter_fn(|req, _| req.query::<String>("fn").is_some())
+                        .path("users/<id>")
+                        .delete(fake_handler),
+                )
+                .push(Router::new().delete(fake_handler)),
+        );
+        let cases = [
+            ("GET", "http://local.host/users/new", Some("/users/new")),
+            ("GET", "http://local.host/users/5", Some("/users/<id>")),
+            ("GET", "http://local.host/users", Some("/users")),
+            (
+                "DELETE",
+                "http://local.host/users/5?fn=1",
+                Some("/users/<id>"),
+            ),
+            ("DELETE", "http://local.host/", Some("/")),
+            ("PUT", "http://local.host/users/5", None),
+            ("PUT", "http://local.host/users", None),
+        ];
+        for (method, url, route) in cases {
+            assert_eq!(detect_both(&router, method, url).as_deref(), route);
+        }
+
+        // Only real `PathFilter`s are indexed, whatever the Debug output of a filter says.
+        struct LookAlike;
+        impl std::fmt::Debug for LookAlike {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("path:users")
+            }
+        }
+        impl Filter for LookAlike {
+            fn filter(&self, _req: &mut Request, _state: &mut PathState) -> bool {
+                true
+            }
+        }
+        let look_alike =
+            Arc::new(Router::new().push(Router::with_filter(LookAlike).get(fake_handler)));
+        assert_eq!(
+            detect_both(&look_alike, "GET", "http://local.host/").as_deref(),
+            Some("/")
+        );
+
+        // Children for other methods are recorded in push order, with the hoops around them.
+        let other_methods = Arc::new(
+            Router::new()
+                .push(
+                    Router::with_filter(filter::post())
+                        .path("x")
+                        .handle(fake_handler),
+                )
+                .push(Router::with_path("x").hoop(fake_handler).get(fake_handler)),
+        );
+        assert_eq!(
+            detect_both(&other_methods, "PUT", "http://local.host/x"),
+            None
+        );
+
+        let mut req = TestClient::get("http://local.host/users/new").build();
+        let mut path_state = PathState::new(req.uri().path());
+        let dm = CompiledRouter::new(router)
+            .detect(&mut req, &mut path_state)
+            .unwrap();
+        assert_eq!(dm.names, ["users.new"]);
+    }
+}
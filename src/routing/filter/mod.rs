@@ -19,6 +19,16 @@ pub trait Filter: fmt::Debug + Send + Sync + 'static {
     fn type_name(&self) -> &'static str {
         std::any::type_name::<Self>()
     }
+    /// `Some` for a `PathFilter`, so routers can read its pattern.
+    #[doc(hidden)]
+    fn as_path_filter(&self) -> Option<&PathFilter> {
+        None
+    }
+    /// `Some` for a `MethodFilter`, so routers can read its method.
+    #[doc(hidden)]
+    fn as_method_filter(&self) -> Option<&MethodFilter> {
+        None
+    }
     fn and<F>(self, other: F) -> And<Self, F>
     where
         Self: Sized,
@@ -9,6 +9,9 @@ use super::Filter;
 pub struct MethodFilter(pub Method);
 
 impl Filter for MethodFilter {
+    fn as_method_filter(&self) -> Option<&MethodFilter> {
+        Some(self)
+    }
     fn filter(&self, req: &mut Request, _state: &mut PathState) -> bool {
         req.method() == self.0
     }
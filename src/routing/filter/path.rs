@@ -4,10 +4,26 @@ use std::sync::Arc;
 
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use regex::Regex;
 
 use crate::http::Request;
-use crate::routing::{Filter, PathState};
+use crate::routing::{Filter, PathParams, PathState, UrlForError};
+
+/// Characters escaped when a param value is written into a path segment.
+const SEGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
 pub trait PathWisp: Send + Sync + 'static + fmt::Debug {
     fn type_id(&self) -> std::any::TypeId {
         std::any::TypeId::of::<Self>()
@@ -16,6 +32,40 @@ pub trait PathWisp: Send + Sync + 'static + fmt::Debug {
         std::any::type_name::<Self>()
     }
     fn detect(&self, state: &mut PathState) -> bool;
+    /// Appends what this wisp matches to `path`, taking values from `params`.
+    ///
+    /// Used by `Router::url_for`, wisps that can not be reversed keep the default.
+    fn fill(&self, _params: &PathParams, _path: &mut String) -> Result<(), UrlForError> {
+        Err(UrlForError::Unsupported(format!("{:?}", self)))
+    }
+}
+
+/// Looks up the value of the param `name`, wildcard names are given without their `*`s.
+fn param<'a>(params: &'a PathParams, name: &str) -> Result<&'a str, UrlForError> {
+    let key = name.trim_start_matches('*');
+    params
+        .get(key)
+        .map(|v| &**v)
+        .ok_or_else(|| UrlForError::MissingParam(key.to_owned()))
+}
+fn invalid_param(name: &str, value: &str) -> UrlForError {
+    UrlForError::InvalidParam {
+        name: name.trim_start_matches('*').to_owned(),
+        value: value.to_owned(),
+    }
+}
+/// Writes `value` percent-encoded, keeping its `/`s when it fills a wildcard.
+fn push_encoded(path: &mut String, value: &str, wildcard: bool) {
+    if wildcard {
+        for (i, part) in value.split('/').enumerate() {
+            if i > 0 {
+                path.push('/');
+            }
+            path.extend(utf8_percent_encode(part, SEGMENT_ENCODE_SET));
+        }
+    } else {
+        path.extend(utf8_percent_encode(value, SEGMENT_ENCODE_SET));
+    }
 }
 
 pub trait WispBuilder: Send + Sync {
@@ -103,6 +153,19 @@ impl PathWisp for RegexWisp {
             }
         }
     }
+    fn fill(&self, params: &PathParams, path: &mut String) -> Result<(), UrlForError> {
+        let value = param(params, &self.name)?;
+        let matched = self
+            .regex
+            .find(value)
+            .map(|m| m.start() == 0 && m.end() == value.len())
+            .unwrap_or(false);
+        if !matched || (value.is_empty() && !self.name.starts_with("**")) {
+            return Err(invalid_param(&self.name, value));
+        }
+        push_encoded(path, value, self.name.starts_with('*'));
+        Ok(())
+    }
 }
 
 pub struct RegexWispBuilder(Regex);
@@ -191,6 +254,18 @@ where
             }
         }
     }
+    fn fill(&self, params: &PathParams, path: &mut String) -> Result<(), UrlForError> {
+        let value = param(params, &self.name)?;
+        let width = value.chars().count();
+        if width < self.min_width
+            || matches!(self.max_width, Some(max_width) if width > max_width)
+            || !value.chars().all(|ch| (self.checker)(ch))
+        {
+            return Err(invalid_param(&self.name, value));
+        }
+        push_encoded(path, value, false);
+        Ok(())
+    }
 }
 
 pub struct CharWispBuilder<C>(Arc<C>);
@@ -287,6 +362,12 @@ impl PathWisp for CombWisp {
         }
         true
     }
+    fn fill(&self, params: &PathParams, path: &mut String) -> Result<(), UrlForError> {
+        for child in &self.0 {
+            child.fill(params, path)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -314,6 +395,14 @@ impl PathWisp for NamedWisp {
             true
         }
     }
+    fn fill(&self, params: &PathParams, path: &mut String) -> Result<(), UrlForError> {
+        let value = param(params, &self.0)?;
+        if value.is_empty() && !self.0.starts_with("**") {
+            return Err(invalid_param(&self.0, value));
+        }
+        push_encoded(path, value, self.0.starts_with('*'));
+        Ok(())
+    }
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -332,6 +421,10 @@ impl PathWisp for ConstWisp {
             false
         }
     }
+    fn fill(&self, _params: &PathParams, path: &mut String) -> Result<(), UrlForError> {
+        path.push_str(&self.0);
+        Ok(())
+    }
 }
 
 struct PathParser {
@@ -634,15 +727,23 @@ impl PathParser {
 pub struct PathFilter {
     raw_value: String,
     path_wisps: Vec<Box<dyn PathWisp>>,
+    trailing_slash: bool,
 }
 
 impl fmt::Debug for PathFilter {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "path:{}", &self.raw_value)
+        write!(f, "path:{}", &self.raw_value)?;
+        if self.trailing_slash && !self.raw_value.ends_with('/') {
+            f.write_str("/")?;
+        }
+        Ok(())
     }
 }
 
 impl Filter for PathFilter {
+    fn as_path_filter(&self) -> Option<&PathFilter> {
+        Some(self)
+    }
     fn filter(&self, _req: &mut Request, state: &mut PathState) -> bool {
         self.detect(state)
     }
@@ -666,8 +767,20 @@ impl PathFilter {
         PathFilter {
             raw_value,
             path_wisps,
+            trailing_slash: false,
         }
     }
+    pub fn raw_value(&self) -> &str {
+        &self.raw_value
+    }
+    pub fn trailing_slash(&self) -> bool {
+        self.trailing_slash
+    }
+    /// Only matches when the request path ends right after this filter, with a slash.
+    pub fn with_trailing_slash(mut self, trailing_slash: bool) -> Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
     pub fn register_wisp_builder<B>(name: impl Into<String>, builder: B)
     where
         B: WispBuilder + 'static,
@@ -697,14 +810,30 @@ impl PathFilter {
                 return false;
             }
         }
+        if self.trailing_slash && !(state.ended() && state.end_slash) {
+            state.cursor = original_cursor;
+            return false;
+        }
         true
     }
+    /// Appends the path this filter matches to `path`, with the wisps filled from `params`.
+    pub fn fill(&self, params: &PathParams, path: &mut String) -> Result<(), UrlForError> {
+        for wisp in &self.path_wisps {
+            let mut segment = String::new();
+            wisp.fill(params, &mut segment)?;
+            if !segment.is_empty() {
+                path.push('/');
+                path.push_str(&segment);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::PathParser;
-    use crate::routing::{PathFilter, PathState};
+    use crate::routing::{PathFilter, PathParams, PathState, UrlForError};
 
     #[test]
     fn test_parse_empty() {
@@ -964,4 +1093,61 @@ mod tests {
         let mut state = PathState::new("/users/12/facebook/insights/23");
         assert!(filter.detect(&mut state));
     }
+    #[test]
+    fn test_detect_trailing_slash() {
+        let filter = PathFilter::new("/users/<id>").with_trailing_slash(true);
+        let mut state = PathState::new("/users/12/");
+        assert!(filter.detect(&mut state));
+        let mut state = PathState::new("/users/12");
+        assert!(!filter.detect(&mut state));
+        assert_eq!(state.cursor, (0, 0));
+        let mut state = PathState::new("/users/12/emails/");
+        assert!(!filter.detect(&mut state));
+    }
+    #[test]
+    fn test_fill() {
+        let params = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+                .collect::<PathParams>()
+        };
+        let filter = PathFilter::new("/users/<id:num(2..=3)>/v<version:/\\d+/>/<**rest>");
+        let mut path = String::new();
+        filter
+            .fill(
+                &params(&[("id", "123"), ("version", "2"), ("rest", "a b/c")]),
+                &mut path,
+            )
+            .unwrap();
+        assert_eq!(path, "/users/123/v2/a%20b/c");
+
+        let mut path = String::new();
+        filter
+            .fill(
+                &params(&[("id", "12"), ("version", "2"), ("rest", "")]),
+                &mut path,
+            )
+            .unwrap();
+        assert_eq!(path, "/users/12/v2");
+
+        for (id, version) in [("1", "2"), ("1234", "2"), ("1a", "2"), ("12", "2x")] {
+            let err = filter
+                .fill(
+                    &params(&[("id", id), ("version", version), ("rest", "")]),
+                    &mut String::new(),
+                )
+                .unwrap_err();
+            assert!(matches!(err, UrlForError::InvalidParam { .. }));
+        }
+        let err = filter
+            .fill(&params(&[("id", "12")]), &mut String::new())
+            .unwrap_err();
+        assert_eq!(err, UrlForError::MissingParam("version".into()));
+
+        let filter = PathFilter::new("/files/<name>");
+        let mut path = String::new();
+        filter.fill(&params(&[("name", "a/b")]), &mut path).unwrap();
+        assert_eq!(path, "/files/a%2Fb");
+    }
 }
@@ -1,14 +1,19 @@
 use std::{borrow::Cow, collections::HashMap, fmt::format, sync::Arc};
 
+mod compiled;
 pub mod filter;
+mod path_policy;
 mod router;
+pub use compiled::CompiledRouter;
 pub use filter::*;
-pub use router::{DetectMatched, Router};
+pub use path_policy::{PathPolicy, TrailingSlash};
+pub use router::{DetectMatched, Router, UrlForError};
 
 use crate::{
+    catcher::Catcher,
     depot::Depot,
     handler::Handler,
-    http::{request::Request, response::Response},
+    http::{request::Request, response::Response, Method},
 };
 
 pub type PathParams = HashMap<String, String>;
@@ -18,6 +23,18 @@ pub struct PathState {
     pub(crate) cursor: (usize, usize),
     pub(crate) params: PathParams,
     pub(crate) end_slash: bool,
+    /// Methods of the routers that matched the path but not the request method.
+    pub(crate) allowed_methods: Vec<Method>,
+    /// Hoops of the routers around the first one recorded in `allowed_methods`, outermost first.
+    pub(crate) hoops: Vec<Arc<dyn Handler>>,
+    /// Hoops of the routers `detect` is in, outermost first.
+    hoop_chain: Vec<Arc<dyn Handler>>,
+    /// Catchers picked by `detect`, innermost router first.
+    pub(crate) catchers: Vec<Arc<dyn Catcher>>,
+    catchers_depth: usize,
+    /// Catchers of the routers `detect` is in, outermost first.
+    catcher_chain: Vec<Arc<dyn Catcher>>,
+    depth: usize,
 }
 
 impl PathState {
@@ -41,6 +58,13 @@ impl PathState {
             cursor: (0, 0),
             params: PathParams::new(),
             end_slash,
+            allowed_methods: Vec::new(),
+            hoops: Vec::new(),
+            hoop_chain: Vec::new(),
+            catchers: Vec::new(),
+            catchers_depth: 0,
+            catcher_chain: Vec::new(),
+            depth: 0,
         }
     }
     pub fn pick(&self) -> Option<&str> {
@@ -91,6 +115,50 @@ impl PathState {
     pub fn ended(&self) -> bool {
         self.cursor.0 >= self.parts.len()
     }
+    /// Methods a request to this path could have used, filled when `detect` finds no match.
+    pub fn allowed_methods(&self) -> &[Method] {
+        &self.allowed_methods
+    }
+    pub(crate) fn allow_method(&mut self, method: Method) {
+        if self.allowed_methods.is_empty() {
+            self.hoops = self.hoop_chain.clone();
+        }
+        if !self.allowed_methods.contains(&method) {
+            self.allowed_methods.push(method);
+        }
+    }
+    /// Hoops to run before answering with `allowed_methods`: those of the routers around the
+    /// first router found for another method.
+    pub fn hoops(&self) -> &[Arc<dyn Handler>] {
+        &self.hoops
+    }
+    /// Catchers of the matched routers, innermost first. When nothing matched, those of the
+    /// deepest routers whose filters passed, the first ones found on a tie.
+    pub fn catchers(&self) -> &[Arc<dyn Catcher>] {
+        &self.catchers
+    }
+    /// Called once the filters of a router passed, the result is given back to `leave_router`.
+    pub(crate) fn enter_router(&mut self, router: &Router) -> (usize, usize) {
+        let chain_lens = (self.catcher_chain.len(), self.hoop_chain.len());
+        self.catcher_chain.extend(router.catchers.iter().cloned());
+        self.hoop_chain.extend(router.hoops.iter().cloned());
+        self.depth += 1;
+        chain_lens
+    }
+    pub(crate) fn leave_router(&mut self, (chain_len, hoop_chain_len): (usize, usize), matched: bool) {
+        let record = if matched {
+            self.catchers_depth != usize::MAX
+        } else {
+            self.depth > self.catchers_depth
+        };
+        if record {
+            self.catchers = self.catcher_chain.iter().rev().cloned().collect();
+            self.catchers_depth = if matched { usize::MAX } else { self.depth };
+        }
+        self.catcher_chain.truncate(chain_len);
+        self.hoop_chain.truncate(hoop_chain_len);
+        self.depth -= 1;
+    }
 }
 
 fn decode_url_path_safely(path: &str) -> String {
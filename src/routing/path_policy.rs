@@ -0,0 +1,175 @@
+use crate::http::uri::Uri;
+
+/// What a `PathPolicy` does with the trailing slash of request paths.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TrailingSlash {
+    /// Paths match with and without a trailing slash.
+    #[default]
+    Ignore,
+    /// Paths without a trailing slash are redirected to the path with one.
+    Add,
+    /// Paths with a trailing slash are redirected to the path without it, except `/` and paths
+    /// matched by a `PathFilter` with `with_trailing_slash(true)`.
+    Remove,
+}
+
+/// The canonical form of request paths a `Service` redirects to.
+///
+/// By default nothing is redirected: empty segments and trailing slashes are ignored when
+/// routing, so every form matches the same routes. Redirects use `Redirect::permanent` and keep
+/// the query string.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PathPolicy {
+    trailing_slash: TrailingSlash,
+    collapse_slashes: bool,
+    lowercase: bool,
+}
+
+impl PathPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn trailing_slash(&self) -> TrailingSlash {
+        self.trailing_slash
+    }
+    pub fn with_trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
+    pub fn collapse_slashes(&self) -> bool {
+        self.collapse_slashes
+    }
+    /// Redirects paths like `/a//b` to `/a/b`.
+    pub fn with_collapse_slashes(mut self, collapse_slashes: bool) -> Self {
+        self.collapse_slashes = collapse_slashes;
+        self
+    }
+    pub fn lowercase(&self) -> bool {
+        self.lowercase
+    }
+    /// Redirects paths with uppercase ASCII letters, percent-encoded bytes are left alone.
+    pub fn with_lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// The canonical form of `path`, `None` when it is canonical already.
+    ///
+    /// Leading slashes are always collapsed, whatever the flags: a `Location` of `//host/` would
+    /// send clients to another host.
+    pub fn normalize(&self, path: &str) -> Option<String> {
+        if *self == Self::default() {
+            return None;
+        }
+        let mut normalized = String::with_capacity(path.len() + 1);
+        let mut escaped = 0;
+        for ch in path.chars() {
+            if ch == '/'
+                && normalized.ends_with('/')
+                && (self.collapse_slashes || normalized.len() == 1)
+            {
+                continue;
+            }
+            if escaped > 0 {
+                escaped -= 1;
+                normalized.push(ch);
+            } else if ch == '%' {
+                escaped = 2;
+                normalized.push(ch);
+            } else if self.lowercase {
+                normalized.push(ch.to_ascii_lowercase());
+            } else {
+                normalized.push(ch);
+            }
+        }
+        match self.trailing_slash {
+            TrailingSlash::Add if !normalized.ends_with('/') => normalized.push('/'),
+            TrailingSlash::Remove => {
+                while normalized.len() > 1 && normalized.ends_with('/') {
+                    normalized.pop();
+                }
+            }
+            _ => {}
+        }
+        (normalized != path).then_some(normalized)
+    }
+
+    /// Where a request to `uri` should be redirected, with its query string.
+    pub(crate) fn redirect_location(&self, uri: &Uri) -> Option<String> {
+        let mut location = self.normalize(uri.path())?;
+        if let Some(query) = uri.query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        Some(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let policy = PathPolicy::new();
+        assert_eq!(policy.normalize("/Users//7/"), None);
+
+        let policy = PathPolicy::new().with_trailing_slash(TrailingSlash::Add);
+        assert_eq!(policy.normalize("/users").as_deref(), Some("/users/"));
+        assert_eq!(policy.normalize("/users/"), None);
+
+        let policy = PathPolicy::new().with_trailing_slash(TrailingSlash::Remove);
+        assert_eq!(policy.normalize("/users//").as_deref(), Some("/users"));
+        assert_eq!(policy.normalize("/"), None);
+
+        let policy = PathPolicy::new()
+            .with_collapse_slashes(true)
+            .with_lowercase(true);
+        assert_eq!(
+            policy.normalize("//Users///A%C3%A9/").as_deref(),
+            Some("/users/a%C3%A9/")
+        );
+        assert_eq!(policy.normalize("/users/a%C3%A9/"), None);
+    }
+
+    #[test]
+    fn test_normalize_leading_slashes() {
+        assert_eq!(PathPolicy::new().normalize("//evil.com/x"), None);
+        let policies = [
+            PathPolicy::new().with_trailing_slash(TrailingSlash::Add),
+            PathPolicy::new().with_trailing_slash(TrailingSlash::Remove),
+            PathPolicy::new().with_collapse_slashes(true),
+            PathPolicy::new().with_lowercase(true),
+        ];
+        for policy in policies {
+            for path in ["//evil.com/x", "///evil.com/x/", "//evil.com//x//"] {
+                let normalized = policy.normalize(path).unwrap();
+                assert!(
+                    normalized.starts_with('/') && !normalized.starts_with("//"),
+                    "{:?} normalized {} to {}",
+                    policy,
+                    path,
+                    normalized
+                );
+            }
+        }
+        let policy = PathPolicy::new().with_trailing_slash(TrailingSlash::Add);
+        assert_eq!(
+            policy.normalize("//evil.com//x").as_deref(),
+            Some("/evil.com//x/")
+        );
+        let policy = PathPolicy::new().with_trailing_slash(TrailingSlash::Remove);
+        assert_eq!(policy.normalize("//").as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn test_redirect_location() {
+        let policy = PathPolicy::new().with_trailing_slash(TrailingSlash::Remove);
+        let uri = "http://local.host/users/?page=2".parse().unwrap();
+        assert_eq!(
+            policy.redirect_location(&uri).as_deref(),
+            Some("/users?page=2")
+        );
+    }
+}
@@ -1,22 +1,50 @@
 use std::fmt::{self, Formatter};
 use std::sync::Arc;
 
+use thiserror::Error;
+
 use super::filter;
-use super::{Filter, FnFilter, PathFilter, PathState};
+use super::{Filter, FnFilter, PathFilter, PathParams, PathState};
 use crate::http::uri::Scheme;
-use crate::http::Request;
-use crate::Handler;
+use crate::http::{Method, Request};
+use crate::{Catcher, Handler};
 
 pub struct Router {
     pub(crate) routers: Vec<Router>,
     pub(crate) filters: Vec<Box<dyn Filter>>,
     pub(crate) hoops: Vec<Arc<dyn Handler>>,
     pub(crate) handler: Option<Arc<dyn Handler>>,
+    pub(crate) name: Option<String>,
+    pub(crate) catchers: Vec<Arc<dyn Catcher>>,
 }
 
 pub struct DetectMatched {
     pub hoops: Vec<Arc<dyn Handler>>,
     pub handler: Arc<dyn Handler>,
+    /// Path patterns of the matched routers joined together, e.g. `/users/<id:num>`.
+    pub route: String,
+    /// Names of the matched routers that have one, outermost first.
+    pub names: Vec<String>,
+    /// Whether a matched path filter only matches paths with a trailing slash.
+    pub trailing_slash: bool,
+}
+
+/// Why `Router::url_for` could not build a URL.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UrlForError {
+    /// No router has the given name.
+    #[error("No router is named `{0}`.")]
+    RouteNotFound(String),
+    /// No value is given for a path param.
+    #[error("Missing value for path param `{0}`.")]
+    MissingParam(String),
+    /// The value is not accepted by the param's wisp.
+    #[error("Value `{value}` is invalid for path param `{name}`.")]
+    InvalidParam { name: String, value: String },
+    /// The path contains a wisp that can not be filled.
+    #[error("Path wisp `{0}` can not be filled.")]
+    Unsupported(String),
 }
 
 impl Default for Router {
@@ -42,6 +70,8 @@ impl Router {
             filters: Vec::new(),
             hoops: Vec::new(),
             handler: None,
+            name: None,
+            catchers: Vec::new(),
         }
     }
 
@@ -66,13 +96,23 @@ impl Router {
         &mut self.filters
     }
 
+    pub fn catchers(&self) -> &Vec<Arc<dyn Catcher>> {
+        &self.catchers
+    }
+    pub fn catchers_mut(&mut self) -> &mut Vec<Arc<dyn Catcher>> {
+        &mut self.catchers
+    }
+
     pub fn detect(&self, req: &mut Request, path_state: &mut PathState) -> Option<DetectMatched> {
-        for filter in &self.filters {
-            if !filter.filter(req, path_state) {
-                return None;
-            }
+        if !self.run_filters(req, path_state) {
+            return None;
         }
-
+        let chain_lens = path_state.enter_router(self);
+        let dm = self.detect_inner(req, path_state);
+        path_state.leave_router(chain_lens, dm.is_some());
+        dm
+    }
+    fn detect_inner(&self, req: &mut Request, path_state: &mut PathState) -> Option<DetectMatched> {
         if !self.routers.is_empty() {
             let original_cursor = path_state.cursor;
             for child in &self.routers {
@@ -80,6 +120,9 @@ impl Router {
                     return Some(DetectMatched {
                         hoops: [&self.hoops[..], &dm.hoops[..]].concat(),
                         handler: dm.handler.clone(),
+                        route: self.join_route(&dm.route),
+                        names: self.name.iter().cloned().chain(dm.names).collect(),
+                        trailing_slash: dm.trailing_slash || self.requires_trailing_slash(),
                     });
                 } else {
                     path_state.cursor = original_cursor;
@@ -92,12 +135,69 @@ impl Router {
                 return Some(DetectMatched {
                     hoops: self.hoops.clone(),
                     handler: handler.clone(),
+                    route: self.join_route(""),
+                    names: self.name.iter().cloned().collect(),
+                    trailing_slash: self.requires_trailing_slash(),
                 });
             }
         }
         None
     }
 
+    /// Runs the filters, recording the method of a router that only misses the request method
+    /// in `path_state.allowed_methods`.
+    pub(crate) fn run_filters(&self, req: &mut Request, path_state: &mut PathState) -> bool {
+        let mut wrong_method = None;
+        for filter in &self.filters {
+            if filter.filter(req, path_state) {
+                continue;
+            }
+            match (&wrong_method, filter_method(&**filter)) {
+                (None, Some(method)) => wrong_method = Some(method),
+                _ => return false,
+            }
+        }
+        match wrong_method {
+            Some(method) => {
+                if self.routers.is_empty() && self.handler.is_some() && path_state.ended() {
+                    path_state.allow_method(method);
+                }
+                false
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn path_filters(&self) -> impl Iterator<Item = &PathFilter> {
+        self.filters.iter().filter_map(|f| f.as_path_filter())
+    }
+    /// Appends the patterns of the `PathFilter`s, e.g. `/users/<id:num>`.
+    pub(crate) fn push_route(&self, route: &mut String) {
+        for filter in self.path_filters() {
+            let pattern = filter.raw_value().trim_matches('/');
+            if !pattern.is_empty() {
+                route.push('/');
+                route.push_str(pattern);
+            }
+        }
+    }
+
+    pub(crate) fn requires_trailing_slash(&self) -> bool {
+        self.path_filters().any(PathFilter::trailing_slash)
+    }
+
+    fn join_route(&self, child_route: &str) -> String {
+        let mut route = String::new();
+        self.push_route(&mut route);
+        if child_route != "/" {
+            route.push_str(child_route);
+        }
+        if route.is_empty() {
+            route.push('/');
+        }
+        route
+    }
+
     pub fn push(mut self, router: Router) -> Self {
         self.routers.push(router);
         self
@@ -116,6 +216,80 @@ impl Router {
         self
     }
 
+    /// Adds a catcher for errors of requests routed through this router. Catchers of inner
+    /// routers run first, then those of `Service::with_catchers`.
+    pub fn catcher<C: Catcher>(mut self, catcher: C) -> Self {
+        self.catchers.push(Arc::new(catcher));
+        self
+    }
+
+    pub fn with_name(name: impl Into<String>) -> Self {
+        Router::new().name(name)
+    }
+    /// Names the router, so it shows up in `Request::matched_names` and can be passed to
+    /// `url_for`.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Builds the path of the router named `name`, filling its params with `params` and
+    /// appending `query`.
+    ///
+    /// Wildcard params are given without their `*`s. Values are checked against the regex or
+    /// char checker of their wisp. The path ends with a slash when the last `PathFilter` does or
+    /// requires one.
+    pub fn url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        query: &[(&str, &str)],
+    ) -> Result<String, UrlForError> {
+        let chain = self
+            .find_named(name)
+            .ok_or_else(|| UrlForError::RouteNotFound(name.to_owned()))?;
+        let params = params
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect::<PathParams>();
+        let mut url = String::new();
+        for router in &chain {
+            for filter in router.path_filters() {
+                filter.fill(&params, &mut url)?;
+            }
+        }
+        let trailing_slash = chain
+            .iter()
+            .flat_map(|router| router.path_filters())
+            .last()
+            .map(|filter| filter.trailing_slash() || filter.raw_value().ends_with('/'))
+            .unwrap_or(false);
+        if url.is_empty() || trailing_slash {
+            url.push('/');
+        }
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(
+                &form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(query)
+                    .finish(),
+            );
+        }
+        Ok(url)
+    }
+    /// The routers from `self` down to the first one named `name`.
+    fn find_named(&self, name: &str) -> Option<Vec<&Router>> {
+        if self.name.as_deref() == Some(name) {
+            return Some(vec![self]);
+        }
+        self.routers.iter().find_map(|child| {
+            child.find_named(name).map(|mut chain| {
+                chain.insert(0, self);
+                chain
+            })
+        })
+    }
+
     pub fn with_path(path: impl Into<String>) -> Self {
         Router::with_filter(PathFilter::new(path))
     }
@@ -165,6 +339,11 @@ impl Router {
     method_server!(get, post, put, delete, patch, head, options);
 }
 
+/// The method a `MethodFilter` accepts.
+fn filter_method(filter: &dyn Filter) -> Option<Method> {
+    filter.as_method_filter().map(|filter| filter.0.clone())
+}
+
 const SYMBOL_DOWN: &str = "│";
 const SYMBOL_TEE: &str = "├";
 const SYMBOL_ELL: &str = "└";
@@ -224,7 +403,7 @@ impl fmt::Debug for Router {
 
 #[cfg(test)]
 mod tests {
-    use super::{PathState, Router};
+    use super::{PathFilter, PathState, Router, UrlForError};
     use crate::handler;
     use crate::test::TestClient;
     use crate::Response;
@@ -497,4 +676,102 @@ mod tests {
         assert!(matched.is_some());
         assert_eq!(path_state.params["p"], "a/b/c");
     }
+
+    #[test]
+    fn test_router_detect_route() {
+        let router = Router::new().name("api").push(
+            Router::with_path("users/<id:num>").name("user").push(
+                Router::with_path("posts").push(
+                    Router::with_path("<**rest>")
+                        .name("user.posts")
+                        .get(fake_handler),
+                ),
+            ),
+        );
+        let mut req = TestClient::get("http://local.host/users/7/posts/2022/10").build();
+        let mut path_state = PathState::new(req.uri().path());
+        let matched = router.detect(&mut req, &mut path_state).unwrap();
+        assert_eq!(matched.route, "/users/<id:num>/posts/<**rest>");
+        assert_eq!(matched.names, ["api", "user", "user.posts"]);
+
+        let router = Router::new().get(fake_handler);
+        let mut req = TestClient::get("http://local.host/").build();
+        let mut path_state = PathState::new(req.uri().path());
+        let matched = router.detect(&mut req, &mut path_state).unwrap();
+        assert_eq!(matched.route, "/");
+        assert!(matched.names.is_empty());
+
+        let mut router = Router::with_path("users").get(fake_handler);
+        router.filters_mut()[0] = Box::new(PathFilter::new("people"));
+        let mut req = TestClient::get("http://local.host/people").build();
+        let mut path_state = PathState::new(req.uri().path());
+        let matched = router.detect(&mut req, &mut path_state).unwrap();
+        assert_eq!(matched.route, "/people");
+    }
+
+    #[test]
+    fn test_router_url_for() {
+        let router = Router::new().push(
+            Router::with_path("users").push(
+                Router::with_path("<id:num>")
+                    .name("user.show")
+                    .get(fake_handler)
+                    .push(
+                        Router::with_path("posts/<slug:/[a-z-]+/>/<**rest>")
+                            .name("user.post")
+                            .get(fake_handler),
+                    ),
+            ),
+        );
+        assert_eq!(
+            router.url_for("user.show", &[("id", "7")], &[]).unwrap(),
+            "/users/7"
+        );
+        let url = router
+            .url_for(
+                "user.post",
+                &[("id", "7"), ("slug", "hello-world"), ("rest", "a/b c")],
+                &[("page", "2"), ("q", "x&y")],
+            )
+            .unwrap();
+        assert_eq!(url, "/users/7/posts/hello-world/a/b%20c?page=2&q=x%26y");
+
+        let mut req = TestClient::get(format!("http://local.host{}", url)).build();
+        let mut path_state = PathState::new(req.uri().path());
+        let matched = router.detect(&mut req, &mut path_state).unwrap();
+        assert_eq!(matched.names, ["user.show", "user.post"]);
+        assert_eq!(path_state.params["**rest"], "a/b c");
+
+        assert_eq!(
+            router.url_for("user.edit", &[], &[]),
+            Err(UrlForError::RouteNotFound("user.edit".into()))
+        );
+        assert_eq!(
+            router.url_for("user.show", &[], &[]),
+            Err(UrlForError::MissingParam("id".into()))
+        );
+        assert_eq!(
+            router.url_for("user.show", &[("id", "x7")], &[]),
+            Err(UrlForError::InvalidParam {
+                name: "id".into(),
+                value: "x7".into()
+            })
+        );
+        let router = router.push(
+            Router::with_filter(PathFilter::new("dirs/<name>").with_trailing_slash(true))
+                .name("dir"),
+        );
+        assert_eq!(
+            router.url_for("dir", &[("name", "a")], &[]).unwrap(),
+            "/dirs/a/"
+        );
+        assert!(matches!(
+            router.url_for(
+                "user.post",
+                &[("id", "7"), ("slug", "Hello"), ("rest", "")],
+                &[]
+            ),
+            Err(UrlForError::InvalidParam { .. })
+        ));
+    }
 }
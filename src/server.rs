@@ -1,11 +1,61 @@
-use crate::{listener::Listener, service::Service, transport::Transport};
+use std::error::Error as StdError;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
 
-use core::future::Future;
+use futures_util::future;
+use hyper::server::accept::Accept;
 use hyper::Server as HyperServer;
-use std::error::Error as StdError;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::sync::CancellationToken;
+
+use crate::addr::SocketAddr;
+use crate::http::uri::Scheme;
+use crate::{listener::Listener, service::Service, transport::Transport};
+
+/// Stops a running `Server` from anywhere; cheap to clone.
+#[derive(Clone, Default)]
+pub struct ServerHandle {
+    inner: Arc<HandleInner>,
+}
+
+#[derive(Default)]
+struct HandleInner {
+    stop: CancellationToken,
+    force: CancellationToken,
+    drain_timeout: Mutex<Option<Duration>>,
+    connections: AtomicUsize,
+}
+
+impl ServerHandle {
+    /// Number of connections currently open.
+    pub fn connections(&self) -> usize {
+        self.inner.connections.load(Ordering::Acquire)
+    }
+    /// Stops accepting new connections and lets the open ones finish their requests.
+    ///
+    /// Connections still open after `timeout` are closed forcibly, `None` waits for all of them.
+    pub fn stop_graceful(&self, timeout: impl Into<Option<Duration>>) {
+        *self.inner.drain_timeout.lock().unwrap() = timeout.into();
+        self.inner.stop.cancel();
+    }
+    /// Stops accepting new connections and closes the open ones immediately.
+    pub fn stop_forcible(&self) {
+        self.inner.stop.cancel();
+        self.inner.force.cancel();
+    }
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stop.is_cancelled()
+    }
+}
 
 pub struct Server<L> {
     listener: L,
+    handle: ServerHandle,
 }
 
 impl<L> Server<L>
@@ -14,9 +64,16 @@ where
     L::Conn: Transport + Send + Unpin + 'static,
     L::Error: Into<Box<(dyn StdError + Send + Sync + 'static)>>,
 {
-    fn new(listener: L) -> Self {
-        Server { listener }
+    pub fn new(listener: L) -> Self {
+        Server {
+            listener,
+            handle: ServerHandle::default(),
+        }
+    }
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
     }
+
     pub async fn serve<S>(self, service: S)
     where
         S: Into<Service>,
@@ -28,8 +85,7 @@ where
     where
         S: Into<Service>,
     {
-        HyperServer::builder(self.listener)
-            .serve(service.into())
+        self.try_serve_with_graceful_shutdown(service, future::pending())
             .await
     }
 
@@ -43,6 +99,7 @@ where
             .unwrap();
     }
 
+    /// Waits for all connections to finish once `signal` resolves, as `ServerHandle::stop_graceful(None)` does.
     pub async fn try_serve_with_graceful_shutdown<S, G>(
         self,
         service: S,
@@ -52,18 +109,168 @@ where
         S: Into<Service>,
         G: Future<Output = ()> + Send + 'static,
     {
-        let server = HyperServer::builder(self.listener).serve(service.into());
-        if let Err(err) = server.with_graceful_shutdown(signal).await {
+        let inner = self.handle.inner.clone();
+        let stop = inner.stop.clone();
+        let incoming = TrackedListener {
+            inner: Box::pin(self.listener),
+            handle: inner.clone(),
+        };
+        let server = HyperServer::builder(incoming)
+            .executor(ServerExec {
+                force: inner.force.clone(),
+            })
+            .serve(service.into())
+            .with_graceful_shutdown(async move {
+                tokio::select! {
+                    _ = stop.cancelled() => {}
+                    _ = signal => {}
+                }
+            });
+        tokio::pin!(server);
+        let drain_timeout = async {
+            inner.stop.cancelled().await;
+            let timeout = *inner.drain_timeout.lock().unwrap();
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            result = &mut server => result,
+            _ = drain_timeout => {
+                tracing::info!(connections = inner.connections.load(Ordering::Acquire), "drain timeout elapsed, closing connections");
+                inner.force.cancel();
+                server.await
+            }
+        };
+        if let Err(err) = &result {
             tracing::error!("server error: {}", err);
-            Err(err)
-        } else {
-            Ok(())
         }
+        result
+    }
+}
+
+/// Spawns connection tasks which are dropped, closing their connection, once `force` is cancelled.
+#[derive(Clone)]
+struct ServerExec {
+    force: CancellationToken,
+}
+
+impl<F> hyper::rt::Executor<F> for ServerExec
+where
+    F: Future + Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        let force = self.force.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = fut => {}
+                _ = force.cancelled() => {}
+            }
+        });
+    }
+}
+
+struct TrackedListener<L> {
+    inner: Pin<Box<L>>,
+    handle: Arc<HandleInner>,
+}
+
+impl<L> Accept for TrackedListener<L>
+where
+    L: Accept,
+{
+    type Conn = TrackedConn<L::Conn>;
+    type Error = L::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let handle = self.handle.clone();
+        self.inner.as_mut().poll_accept(cx).map(|conn| {
+            conn.map(|conn| {
+                conn.map(|conn| {
+                    handle.connections.fetch_add(1, Ordering::AcqRel);
+                    TrackedConn {
+                        inner: conn,
+                        handle,
+                    }
+                })
+            })
+        })
+    }
+}
+
+struct TrackedConn<C> {
+    inner: C,
+    handle: Arc<HandleInner>,
+}
+
+impl<C> Drop for TrackedConn<C> {
+    fn drop(&mut self) {
+        self.handle.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl<C> Transport for TrackedConn<C>
+where
+    C: Transport + Unpin,
+{
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.inner.remote_addr()
+    }
+    fn scheme(&self) -> Scheme {
+        self.inner.scheme()
+    }
+}
+
+impl<C> AsyncRead for TrackedConn<C>
+where
+    C: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<C> AsyncWrite for TrackedConn<C>
+where
+    C: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(cx, bufs)
+    }
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use serde::Serialize;
 
     use crate::prelude::*;
@@ -159,4 +366,72 @@ mod tests {
         assert!(result.contains("<code>404</code>"));
         server.abort();
     }
+
+    #[tokio::test]
+    async fn test_server_handle_graceful() {
+        #[handler(internal)]
+        async fn slow() -> &'static str {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            "done"
+        }
+        let listener = TcpListener::bind("127.0.0.1:0");
+        let addr = listener.local_addr();
+        let server = Server::new(listener);
+        let handle = server.handle();
+        let server = tokio::spawn(server.serve(Router::new().get(slow)));
+
+        let url = format!("http://{}", addr);
+        let request = tokio::spawn(reqwest::get(url.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(handle.connections(), 1);
+
+        handle.stop_graceful(None);
+        assert!(handle.is_stopped());
+        let res = request.await.unwrap().unwrap();
+        assert_eq!(res.text().await.unwrap(), "done");
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(handle.connections(), 0);
+        assert!(reqwest::get(url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_server_handle_drain_timeout() {
+        #[handler(internal)]
+        async fn stuck() -> &'static str {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            "done"
+        }
+        let listener = TcpListener::bind("127.0.0.1:0");
+        let addr = listener.local_addr();
+        let server = Server::new(listener);
+        let handle = server.handle();
+        let server = tokio::spawn(server.serve(Router::new().get(stuck)));
+
+        let request = tokio::spawn(reqwest::get(format!("http://{}", addr)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(handle.connections(), 1);
+
+        handle.stop_graceful(Duration::from_millis(100));
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(request.await.unwrap().is_err());
+        assert_eq!(handle.connections(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_server_handle_forcible() {
+        let listener = TcpListener::bind("127.0.0.1:0");
+        let server = Server::new(listener);
+        let handle = server.handle();
+        handle.clone().stop_forcible();
+        tokio::time::timeout(Duration::from_secs(5), server.serve(Router::new()))
+            .await
+            .unwrap();
+        assert_eq!(handle.connections(), 0);
+    }
 }
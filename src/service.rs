@@ -7,31 +7,36 @@ use futures_util::future;
 
 use crate::addr::SocketAddr;
 use crate::catcher::CatcherImpl;
-use crate::http::header::CONTENT_TYPE;
-use crate::http::{Mime, Request, Response, StatusCode};
-use crate::routing::{FlowCtrl, PathState, Router};
+use crate::http::header::{HeaderValue, ALLOW, CONTENT_TYPE};
+use crate::http::uri::Scheme;
+use crate::http::{Method, Mime, Request, Response, StatusCode, StatusError};
+use crate::routing::{CompiledRouter, FlowCtrl, PathPolicy, PathState, Router, TrailingSlash};
 use crate::transport::Transport;
-use crate::{Catcher, Depot};
+use crate::writer::Redirect;
+use crate::{async_trait, Catcher, Depot, Handler};
 
 pub struct Service {
-    pub(crate) router: Arc<Router>,
+    pub(crate) router: Arc<CompiledRouter>,
     pub(crate) catchers: Arc<Vec<Box<dyn Catcher>>>,
     pub(crate) allowed_media_types: Arc<Vec<Mime>>,
+    pub(crate) path_policy: PathPolicy,
 }
 
 impl Service {
+    /// Freezes `router` into a `CompiledRouter`, so requests are matched through its index.
     pub fn new<T>(router: T) -> Service
     where
         T: Into<Arc<Router>>,
     {
         Service {
-            router: router.into(),
+            router: Arc::new(CompiledRouter::new(router)),
             catchers: Arc::new(vec![]),
             allowed_media_types: Arc::new(vec![]),
+            path_policy: PathPolicy::default(),
         }
     }
     pub fn router(&self) -> Arc<Router> {
-        self.router.clone()
+        self.router.router().clone()
     }
     pub fn with_catchers<T>(mut self, catchers: T) -> Self
     where
@@ -53,12 +58,22 @@ impl Service {
     pub fn allowed_media_types(&self) -> Arc<Vec<Mime>> {
         self.allowed_media_types.clone()
     }
+    /// Requests to paths that are not in the policy's canonical form are redirected to it.
+    pub fn with_path_policy(mut self, path_policy: PathPolicy) -> Self {
+        self.path_policy = path_policy;
+        self
+    }
+    pub fn path_policy(&self) -> PathPolicy {
+        self.path_policy
+    }
     pub fn hyper_handle(&self, remote_addr: Option<SocketAddr>) -> HyperHandler {
         HyperHandler {
             remote_addr,
+            scheme: Scheme::HTTP,
             router: self.router.clone(),
             catchers: self.catchers.clone(),
             allowed_media_types: self.allowed_media_types.clone(),
+            path_policy: self.path_policy,
         }
     }
     pub async fn handle(&self, request: impl Into<Request>) -> Response {
@@ -69,9 +84,12 @@ impl Service {
 #[derive(Clone)]
 pub struct HyperHandler {
     pub(crate) remote_addr: Option<SocketAddr>,
-    pub(crate) router: Arc<Router>,
+    /// Scheme of the connection, `https` behind a TLS listener.
+    pub(crate) scheme: Scheme,
+    pub(crate) router: Arc<CompiledRouter>,
     pub(crate) catchers: Arc<Vec<Box<dyn Catcher>>>,
     pub(crate) allowed_media_types: Arc<Vec<Mime>>,
+    pub(crate) path_policy: PathPolicy,
 }
 
 impl HyperHandler {
@@ -79,16 +97,35 @@ impl HyperHandler {
         let catchers = self.catchers.clone();
         let allowed_media_types = self.allowed_media_types.clone();
         req.remote_addr = self.remote_addr.clone();
+        req.scheme = self.scheme.clone();
         let mut res = Response::with_cookies(req.cookies.clone());
         let mut depot = Depot::new();
         let mut path_state = PathState::new(req.uri().path());
         let router = self.router.clone();
+        let path_policy = self.path_policy;
 
         async move {
-            if let Some(dm) = router.detect(&mut req, &mut path_state) {
+            let detected = router.detect(&mut req, &mut path_state);
+            // A route asking for a trailing slash keeps it, whatever the policy says.
+            let redirect = match &detected {
+                Some(dm) if dm.trailing_slash => path_policy
+                    .with_trailing_slash(TrailingSlash::Ignore)
+                    .redirect_location(req.uri()),
+                _ => path_policy.redirect_location(req.uri()),
+            };
+            if let Some(location) = redirect {
+                res.render(Redirect::permanent(location));
+            } else if let Some(dm) = detected {
                 req.params = path_state.params;
+                req.matched_route = Some(dm.route);
+                req.matched_names = dm.names;
                 let mut ctrl = FlowCtrl::new([&dm.hoops[..], &[dm.handler]].concat());
                 ctrl.call_next(&mut req, &mut depot, &mut res).await;
+            } else if !path_state.allowed_methods.is_empty() {
+                // Hoops like `Cors` get to see preflight requests before they are answered.
+                let answer = AllowedMethods(std::mem::take(&mut path_state.allowed_methods));
+                let mut ctrl = FlowCtrl::new([&path_state.hoops[..], &[Arc::new(answer)]].concat());
+                ctrl.call_next(&mut req, &mut depot, &mut res).await;
             } else {
                 res.set_status_code(StatusCode::NOT_FOUND);
             }
@@ -133,15 +170,16 @@ impl HyperHandler {
                 );
             }
             if res.body.is_none() && has_error {
+                let route_catchers = path_state.catchers.iter().map(|c| &**c);
                 let mut catch = false;
-                for catcher in catchers.iter() {
-                    if catcher.catch(&req, &depot, &mut res) {
+                for catcher in route_catchers.chain(catchers.iter().map(|c| &**c)) {
+                    if catcher.catch(&mut req, &mut depot, &mut res).await {
                         catch = true;
                         break;
                     }
                 }
                 if !catch {
-                    CatcherImpl.catch(&req, &depot, &mut res);
+                    CatcherImpl.catch(&mut req, &mut depot, &mut res).await;
                 }
             }
             if let hyper::Method::HEAD = *req.method() {
@@ -155,6 +193,48 @@ impl HyperHandler {
     }
 }
 
+/// Answers a request whose path matched routers for other methods only: `OPTIONS` gets the
+/// `Allow` header, other methods a 405 error as well.
+///
+/// It runs after the hoops of the routers around the first router found for another method.
+struct AllowedMethods(Vec<Method>);
+
+#[async_trait]
+impl Handler for AllowedMethods {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        _depot: &mut Depot,
+        res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        answer_allowed_methods(req, res, self.0.clone());
+    }
+}
+
+fn answer_allowed_methods(req: &Request, res: &mut Response, mut methods: Vec<Method>) {
+    if !methods.contains(&Method::OPTIONS) {
+        methods.push(Method::OPTIONS);
+    }
+    methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    let allow = methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if let Ok(value) = HeaderValue::from_str(&allow) {
+        res.headers_mut().insert(ALLOW, value);
+    }
+    if req.method() == Method::OPTIONS {
+        res.set_status_code(StatusCode::NO_CONTENT);
+    } else {
+        res.set_status_error(
+            StatusError::method_not_allowed()
+                .with_detail(format!("The allowed methods are: {}.", allow)),
+        );
+    }
+}
+
 impl hyper::service::Service<hyper::Request<hyper::body::Body>> for HyperHandler {
     type Response = hyper::Response<hyper::body::Body>;
     type Error = hyper::Error;
@@ -191,7 +271,9 @@ where
         Ok(()).into()
     }
     fn call(&mut self, req: &'t T) -> Self::Future {
-        future::ok(self.hyper_handle(req.remote_addr()))
+        let mut handler = self.hyper_handle(req.remote_addr());
+        handler.scheme = req.scheme();
+        future::ok(handler)
     }
 }
 
@@ -288,4 +370,107 @@ mod tests {
         let content = access(&service, "3").await;
         assert_eq!(content, "before1before2before3");
     }
+
+    #[tokio::test]
+    async fn test_allowed_methods() {
+        #[handler(internal)]
+        async fn hello() -> &'static str {
+            "hello"
+        }
+        let router = Router::with_path("users")
+            .get(hello)
+            .post(hello)
+            .push(Router::with_path("<id>").delete(hello))
+            .push(Router::with_path("<id>/avatar").options(hello));
+        let service = Service::new(router);
+
+        let mut res = TestClient::put("http://127.0.0.1:7979/users")
+            .add_header("accept", "application/json", true)
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::METHOD_NOT_ALLOWED));
+        assert_eq!(res.headers()["allow"], "GET, OPTIONS, POST");
+        assert_eq!(res.headers()["content-type"], "application/json");
+        assert!(res
+            .take_string()
+            .await
+            .unwrap()
+            .contains("The allowed methods are: GET, OPTIONS, POST."));
+
+        let res = TestClient::options("http://127.0.0.1:7979/users")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NO_CONTENT));
+        assert_eq!(res.headers()["allow"], "GET, OPTIONS, POST");
+
+        let res = TestClient::get("http://127.0.0.1:7979/users/1")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::METHOD_NOT_ALLOWED));
+        assert_eq!(res.headers()["allow"], "DELETE, OPTIONS");
+
+        let mut res = TestClient::options("http://127.0.0.1:7979/users/1/avatar")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+
+        let res = TestClient::get("http://127.0.0.1:7979/posts")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NOT_FOUND));
+        assert!(!res.headers().contains_key("allow"));
+    }
+
+    #[tokio::test]
+    async fn test_path_policy() {
+        use crate::routing::{PathFilter, PathPolicy, TrailingSlash};
+
+        #[handler(internal)]
+        async fn hello() -> &'static str {
+            "hello"
+        }
+        let router = Router::new()
+            .push(Router::with_path("users/<id>").get(hello))
+            .push(
+                Router::with_filter(PathFilter::new("dirs/<name>").with_trailing_slash(true))
+                    .get(hello),
+            );
+        let service = Service::new(router).with_path_policy(
+            PathPolicy::new()
+                .with_trailing_slash(TrailingSlash::Remove)
+                .with_collapse_slashes(true)
+                .with_lowercase(true),
+        );
+
+        let res = TestClient::get("http://127.0.0.1:7979/Users//7/?page=2")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::PERMANENT_REDIRECT));
+        assert_eq!(res.headers()["location"], "/users/7?page=2");
+        let mut res = TestClient::get("http://127.0.0.1:7979/users/7?page=2")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+
+        // The route's own trailing slash wins over the policy, other normalizations still apply.
+        let mut res = TestClient::get("http://127.0.0.1:7979/dirs/a/")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+        let res = TestClient::get("http://127.0.0.1:7979/dirs//a/")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::PERMANENT_REDIRECT));
+        assert_eq!(res.headers()["location"], "/dirs/a/");
+        let res = TestClient::get("http://127.0.0.1:7979/dirs/a")
+            .send(&service)
+            .await;
+        assert_eq!(res.status_code(), Some(StatusCode::NOT_FOUND));
+
+        let service = Service::new(service.router());
+        let mut res = TestClient::get("http://127.0.0.1:7979/users//7/")
+            .send(&service)
+            .await;
+        assert_eq!(res.take_string().await.unwrap(), "hello");
+    }
 }
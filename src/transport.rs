@@ -2,13 +2,24 @@ use hyper::server::conn::AddrStream;
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::addr::SocketAddr;
+use crate::http::uri::Scheme;
 
 pub trait Transport: AsyncRead + AsyncWrite {
     fn remote_addr(&self) -> Option<SocketAddr>;
+    /// `https` for TLS connections.
+    fn scheme(&self) -> Scheme {
+        Scheme::HTTP
+    }
 }
 
 impl Transport for AddrStream {
     fn remote_addr(&self) -> Option<SocketAddr> {
         Some(self.remote_addr().into())
     }
-}
\ No newline at end of file
+}
+#[cfg(unix)]
+impl Transport for tokio::net::UnixStream {
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr().ok().map(Into::into)
+    }
+}